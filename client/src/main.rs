@@ -3,7 +3,6 @@ extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
 
-#[macro_use]
 extern crate common;
 
 pub mod net;
@@ -47,7 +46,8 @@ impl Game {
             to_socket_addr(BIND_ADDR, SERVER_PORT),
         );
         client.send(Packet::Hello {
-            name: USERNAME.to_string(),
+            protocol: protocol_fingerprint(),
+            name: USERNAME,
         });
 
         Game {
@@ -60,7 +60,9 @@ impl Game {
 
     pub fn tick(&mut self, tick_config: TickConfig) {
         self.client.tick();
-        self.ecs.tick(&tick_config);
+        if let Err(e) = self.ecs.tick(&tick_config) {
+            eprintln!("system skipped: {}", e);
+        }
     }
 
     pub fn render(&mut self, args: &RenderArgs) {
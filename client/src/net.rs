@@ -17,12 +17,24 @@ impl Client {
     }
 
     pub fn tick(&mut self) {
-        for (packet, _) in self.socket.poll().iter() {
+        while let Some((packet, src)) = self.socket.recv() {
+            let packet = match packet {
+                Ok(packet) => packet,
+                // A malformed packet shouldn't take us down with it.
+                Err(e) => {
+                    eprintln!("dropping malformed packet from {}: {}", src, e);
+                    continue;
+                }
+            };
             match packet {
                 Packet::Hello { .. } => eprintln!("received Hello from server"),
                 Packet::HelloAck { .. } => println!("received {:?}", packet),
                 Packet::CreateEntity { .. } => println!("received {:?}", packet),
                 Packet::SetComponent { .. } => println!("received {:?}", packet),
+                Packet::HelloReject { reason } => {
+                    eprintln!("server rejected us: {}", reason);
+                    std::process::exit(1);
+                }
             };
         }
     }
@@ -1,11 +1,9 @@
-use std::any::TypeId;
-
 use graphics::Context;
 use opengl_graphics::GlGraphics;
 use piston::input::RenderArgs;
 
 use common::ecs::component::{PositionComponent, RenderComponent};
-use common::ecs::{Ecs, Entity, EntityMap};
+use common::ecs::Ecs;
 
 pub struct Renderer;
 
@@ -21,21 +19,14 @@ impl Renderer {
 
         gl.draw(args.viewport(), |c, gl| {
             clear(GREEN, gl);
+            match ecs
+                .entity_map
+                .try_query::<(&PositionComponent, &RenderComponent)>()
             {
-                let comp_constraints = self.comp_constraints();
-                let filtered_entities = ecs.entities().filter(|e| {
-                    let comp_map = ecs.entity_map.borrow(e).unwrap();
-                    for comp_type_id in comp_constraints.iter() {
-                        if !comp_map.has_type_id(comp_type_id) {
-                            return false;
-                        }
-                    }
-                    true
-                });
-
-                for entity in filtered_entities {
-                    self.render_single(gl, c, args, &ecs.entity_map, &entity)
-                }
+                Ok(mut query) => query.for_each(|_, (pos_comp, render_comp)| {
+                    self.render_single(gl, c, args, pos_comp, render_comp)
+                }),
+                Err(e) => eprintln!("can't render entities: {}", e),
             }
         });
     }
@@ -45,22 +36,14 @@ impl Renderer {
         gl: &mut GlGraphics,
         c: Context,
         args: &RenderArgs,
-        entity_map: &EntityMap,
-        entity: &Entity,
+        pos_comp: &PositionComponent,
+        render_comp: &RenderComponent,
     ) {
         use graphics::*;
 
-        let comp_map = entity_map.borrow(entity).unwrap();
-        let pos_comp = comp_map.borrow::<PositionComponent>();
-        let render_comp = comp_map.borrow::<RenderComponent>();
-
         let (x, y) = ((args.width / 2) as f64, (args.height / 2) as f64);
         let square = rectangle::square(0.0, 0.0, render_comp.size);
         let transform = c.transform.trans(x, y).trans(-pos_comp.x, -pos_comp.y);
         rectangle(render_comp.color, square, transform, gl);
     }
-
-    fn comp_constraints(&self) -> Vec<TypeId> {
-        type_id_vec![PositionComponent, RenderComponent]
-    }
 }
@@ -0,0 +1,183 @@
+//! Compares the column-per-component storage in `EntityMap` against the layout it replaced, where
+//! each entity had its own `HashMap` of boxed components.
+//!
+//! Run with `cargo bench -p common`.
+
+#[macro_use]
+extern crate common;
+#[macro_use]
+extern crate criterion;
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+
+use criterion::{black_box, Criterion};
+
+use common::ecs::alloc::{GenerationalIndexAllocator, GenerationalIndexArray};
+use common::ecs::{Ecs, Entity};
+
+const ENTITIES: usize = 10_000;
+
+struct Position {
+    x: f64,
+    y: f64,
+}
+
+struct Velocity {
+    dx: f64,
+    dy: f64,
+}
+
+// Something for the entities to have besides what's being iterated over.
+struct Solid;
+
+/// The old per-entity storage.
+mod legacy {
+    use super::*;
+
+    pub struct ComponentMap {
+        data: HashMap<TypeId, Box<dyn Any>>,
+    }
+
+    impl ComponentMap {
+        pub fn borrow_mut<C: Any>(&mut self) -> &mut C {
+            self.data
+                .get_mut(&TypeId::of::<C>())
+                .map(|c| c.downcast_mut().unwrap())
+                .unwrap()
+        }
+
+        pub fn borrow<C: Any>(&self) -> &C {
+            self.data
+                .get(&TypeId::of::<C>())
+                .map(|c| c.downcast_ref().unwrap())
+                .unwrap()
+        }
+
+        pub fn set<C: Any>(&mut self, comp: C) {
+            self.data.insert(TypeId::of::<C>(), Box::new(comp));
+        }
+
+        pub fn has_type_id(&self, type_id: &TypeId) -> bool {
+            self.data.contains_key(type_id)
+        }
+    }
+
+    pub struct World {
+        pub entity_map: GenerationalIndexArray<ComponentMap>,
+        pub entity_allocator: GenerationalIndexAllocator,
+    }
+
+    impl World {
+        pub fn new() -> Self {
+            Self {
+                entity_map: GenerationalIndexArray::new(),
+                entity_allocator: GenerationalIndexAllocator::new(),
+            }
+        }
+
+        pub fn create_entity(&mut self) -> Entity {
+            let result = self.entity_allocator.allocate();
+            self.entity_map.set(
+                &result,
+                ComponentMap {
+                    data: HashMap::new(),
+                },
+            );
+            result
+        }
+
+        /// Filters the entities the way `Ecs::tick` used to.
+        pub fn entities_with(&self, comp_constraints: &[TypeId]) -> Vec<Entity> {
+            self.entity_allocator
+                .entries()
+                .filter(|e| {
+                    let comp_map = self.entity_map.borrow(e).unwrap();
+                    comp_constraints.iter().all(|t| comp_map.has_type_id(t))
+                })
+                .collect()
+        }
+    }
+}
+
+// Every entity has a position and is solid, but only every other one moves.
+fn populate_legacy() -> legacy::World {
+    let mut world = legacy::World::new();
+    for i in 0..ENTITIES {
+        let entity = world.create_entity();
+        let mut comp_map = world.entity_map.borrow_mut(&entity).unwrap();
+        comp_map.set(Position { x: 0.0, y: 0.0 });
+        comp_map.set(Solid);
+        if i % 2 == 0 {
+            comp_map.set(Velocity { dx: 1.0, dy: 2.0 });
+        }
+    }
+    world
+}
+
+fn populate() -> Ecs<()> {
+    let mut ecs = Ecs::new();
+    for i in 0..ENTITIES {
+        let entity = ecs.create_entity();
+        ecs.entity_map.set(&entity, Position { x: 0.0, y: 0.0 });
+        ecs.entity_map.set(&entity, Solid);
+        if i % 2 == 0 {
+            ecs.entity_map.set(&entity, Velocity { dx: 1.0, dy: 2.0 });
+        }
+    }
+    ecs
+}
+
+fn create(c: &mut Criterion) {
+    c.bench_function("create/legacy", |b| b.iter(populate_legacy));
+    c.bench_function("create/columns", |b| b.iter(populate));
+}
+
+fn movement(c: &mut Criterion) {
+    let constraints = type_id_vec![Position, Velocity];
+
+    let world = populate_legacy();
+    c.bench_function("movement/legacy", |b| {
+        b.iter(|| {
+            for entity in world.entities_with(&constraints) {
+                let mut comp_map = world.entity_map.borrow_mut(&entity).unwrap();
+                let (dx, dy) = {
+                    let vel = comp_map.borrow::<Velocity>();
+                    (vel.dx, vel.dy)
+                };
+                let pos = comp_map.borrow_mut::<Position>();
+                pos.x += dx;
+                pos.y += dy;
+            }
+        })
+    });
+
+    let ecs = populate();
+    c.bench_function("movement/columns", |b| {
+        b.iter(|| {
+            ecs.entity_map
+                .query::<(&mut Position, &Velocity)>()
+                .for_each(|_, (pos, vel)| {
+                    pos.x += vel.dx;
+                    pos.y += vel.dy;
+                })
+        })
+    });
+}
+
+fn filter(c: &mut Criterion) {
+    let constraints = type_id_vec![Position, Velocity];
+
+    let world = populate_legacy();
+    c.bench_function("filter/legacy", |b| {
+        b.iter(|| black_box(world.entities_with(&constraints)))
+    });
+
+    let ecs = populate();
+    c.bench_function("filter/columns", |b| {
+        b.iter(|| black_box(ecs.entity_map.entities_with(&constraints)))
+    });
+}
+
+criterion_group!(benches, create, movement, filter);
+criterion_main!(benches);
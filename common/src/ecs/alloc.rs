@@ -1,8 +1,10 @@
 use std::cell::{Ref, RefCell, RefMut};
 
-#[derive(Clone, Debug, Serde)]
+#[derive(Clone, Debug, PartialEq, Eq, Serde)]
 pub struct GenerationalIndex {
+    #[serde(varint)]
     pub idx: usize,
+    #[serde(varint)]
     pub gen: u64,
 }
 
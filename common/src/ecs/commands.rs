@@ -0,0 +1,105 @@
+use std::sync::Mutex;
+
+use super::alloc::GenerationalIndexAllocator;
+use super::{destroy, Component, Entity, EntityMap};
+
+enum Command {
+    Create(Entity),
+    Destroy(Entity),
+    // Setting and removing components are done through closures, since they depend on the
+    // component type.
+    Edit(Box<dyn FnOnce(&mut EntityMap) + Send>),
+}
+
+/// Changes to the entities that a system wants to make, but can't while other systems might be
+/// looking at them.
+///
+/// They're applied in the order they were queued, once every system in the stage has finished
+/// (see `Ecs::tick`).  Changes to entities that no longer exist by then are ignored.  If the
+/// commands are dropped without being applied (say, because the system panicked), the entities
+/// they created are freed again.
+pub struct Commands<'w> {
+    entity_allocator: &'w Mutex<GenerationalIndexAllocator>,
+    queue: Vec<Command>,
+    // Entities that have been allocated, but not inserted into the `EntityMap` yet.
+    reserved: Vec<Entity>,
+}
+
+impl<'w> Commands<'w> {
+    pub(crate) fn new(entity_allocator: &'w Mutex<GenerationalIndexAllocator>) -> Self {
+        Self {
+            entity_allocator,
+            queue: Vec::new(),
+            reserved: Vec::new(),
+        }
+    }
+
+    /// Returns a new entity, which can have components set on it straight away.  It isn't live
+    /// (so it won't show up in queries) until the commands are applied.
+    pub fn create_entity(&mut self) -> Entity {
+        let result = self.entity_allocator.lock().unwrap().allocate();
+        self.queue.push(Command::Create(result.clone()));
+        self.reserved.push(result.clone());
+        result
+    }
+
+    pub fn destroy_entity(&mut self, entity: Entity) {
+        self.queue.push(Command::Destroy(entity));
+    }
+
+    /// Sets `entity`'s `C`, replacing the one it already had.
+    pub fn set<C: Component>(&mut self, entity: &Entity, comp: C) {
+        let entity = entity.clone();
+        self.queue.push(Command::Edit(Box::new(move |entity_map| {
+            entity_map.set(&entity, comp);
+        })));
+    }
+
+    pub fn remove<C: Component>(&mut self, entity: &Entity) {
+        let entity = entity.clone();
+        self.queue.push(Command::Edit(Box::new(move |entity_map| {
+            entity_map.remove::<C>(&entity);
+        })));
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub(crate) fn apply(mut self, entity_map: &mut EntityMap) {
+        // Every reserved entity gets inserted below, so there's nothing left to free afterwards.
+        self.reserved.clear();
+        for command in std::mem::take(&mut self.queue) {
+            match command {
+                Command::Create(entity) => entity_map.insert_entity(&entity),
+                Command::Destroy(entity) => {
+                    if entity_map.is_live(&entity) {
+                        let mut entity_allocator = self.entity_allocator.lock().unwrap();
+                        destroy(entity_map, &mut entity_allocator, &entity);
+                    }
+                }
+                Command::Edit(edit) => edit(entity_map),
+            }
+        }
+    }
+}
+
+impl Drop for Commands<'_> {
+    fn drop(&mut self) {
+        if self.reserved.is_empty() {
+            return;
+        }
+        // Don't panic again if the allocator was poisoned by whatever stopped us being applied.
+        let mut entity_allocator = match self.entity_allocator.lock() {
+            Ok(entity_allocator) => entity_allocator,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for entity in self.reserved.iter() {
+            entity_allocator.deallocate(entity);
+        }
+    }
+}
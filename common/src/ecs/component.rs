@@ -2,20 +2,32 @@ extern crate serde;
 
 use crate::random_mob::RandomMobComponent;
 
+// Tags are pinned for the same reason as `Packet`'s.
 #[derive(Debug, Serde)]
 pub enum Component {
+    #[serde(tag = 0)]
     PositionComponent(PositionComponent),
+    #[serde(tag = 1)]
     RenderComponent(RenderComponent),
+    #[serde(tag = 2)]
     RandomMobComponent(RandomMobComponent),
 }
 
-#[derive(Debug, Serde)]
+/// How far from the origin positions can be sent.  Anything further out gets clamped.
+pub const POSITION_LIMIT: f64 = 65536.0;
+
+// Positions are sent as fixed-point values, to a precision of about 1/32768 of a pixel.
+#[derive(Debug, PartialEq, Serde)]
+#[serde(delta)]
 pub struct PositionComponent {
+    #[serde(quantize(min = "-POSITION_LIMIT", max = "POSITION_LIMIT", bits = 32))]
     pub x: f64,
+    #[serde(quantize(min = "-POSITION_LIMIT", max = "POSITION_LIMIT", bits = 32))]
     pub y: f64,
 }
 
-#[derive(Debug, Serde)]
+#[derive(Debug, PartialEq, Serde)]
+#[serde(delta)]
 pub struct RenderComponent {
     pub color: [f32; 4],
     pub size: f64,
@@ -0,0 +1,66 @@
+use std::error::Error;
+use std::fmt;
+
+use super::Entity;
+
+/// Everything that can go wrong when looking up an entity's components.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EcsError {
+    /// The entity was never created.
+    NoSuchEntity { entity: Entity },
+    /// The entity has been destroyed (and its slot might already belong to a newer entity).
+    StaleGeneration { entity: Entity },
+    /// The entity is live, but doesn't have the component.
+    MissingComponent {
+        entity: Entity,
+        component: &'static str,
+    },
+    /// The component's column is borrowed in a way that conflicts with the new borrow (e.g., a
+    /// query that borrows the same component mutably twice).
+    AlreadyBorrowed { component: &'static str },
+    /// A system is ordered before or after a label that no system has.
+    UnknownLabel {
+        system: &'static str,
+        label: &'static str,
+    },
+    /// The systems' `before`/`after` constraints go around in a circle, so they can't all be met.
+    OrderCycle { systems: Vec<&'static str> },
+}
+
+impl fmt::Display for EcsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EcsError::NoSuchEntity { ref entity } => write!(
+                f,
+                "entity {} (generation {}) doesn't exist",
+                entity.idx, entity.gen
+            ),
+            EcsError::StaleGeneration { ref entity } => write!(
+                f,
+                "entity {} (generation {}) has been destroyed",
+                entity.idx, entity.gen
+            ),
+            EcsError::MissingComponent {
+                ref entity,
+                component,
+            } => write!(
+                f,
+                "entity {} (generation {}) has no `{}`",
+                entity.idx, entity.gen, component
+            ),
+            EcsError::AlreadyBorrowed { component } => {
+                write!(f, "`{}` components are already borrowed", component)
+            }
+            EcsError::UnknownLabel { system, label } => write!(
+                f,
+                "`{}` is ordered against \"{}\", but no system has that label",
+                system, label
+            ),
+            EcsError::OrderCycle { ref systems } => {
+                write!(f, "systems are ordered in a cycle: {}", systems.join(", "))
+            }
+        }
+    }
+}
+
+impl Error for EcsError {}
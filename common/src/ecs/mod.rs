@@ -1,135 +1,714 @@
 pub mod alloc;
+pub mod commands;
 pub mod component;
+pub mod error;
+pub mod query;
+pub mod schedule;
+pub mod storage;
 pub mod system;
 
-use std::any::{Any, TypeId};
+use std::any::{type_name, Any, TypeId};
 use std::collections::HashMap;
+use std::sync::Mutex;
 
-use self::alloc::{GenerationalIndex, GenerationalIndexAllocator, GenerationalIndexArray};
-use self::system::System;
+use atomic_refcell::{AtomicRef, AtomicRefCell, AtomicRefMut};
+
+use self::alloc::{GenerationalIndex, GenerationalIndexAllocator};
+use self::commands::Commands;
+use self::error::EcsError;
+use self::query::{Fetch, Query};
+use self::storage::{AnyColumn, Column};
+use self::system::SystemDesc;
 
 pub const LEVEL_WIDTH: usize = 32;
 pub const LEVEL_HEIGHT: usize = 32;
 
 pub type Entity = GenerationalIndex;
-pub type EntityMap = GenerationalIndexArray<ComponentMap>;
 
 // Here we have to extend `Any`, because otherwise we need to specify a static lifetime bound to
 // functions generic on `Component`.
 //
 // TODO: Why the fuck do we need to do that?
 // Is `Any` implied to have a static lifetime?
-pub trait Component: Any {}
-impl<T: Any> Component for T {}
+//
+// They also have to be `Send` and `Sync`, so systems can run on other threads.
+pub trait Component: Any + Send + Sync {}
+impl<T: Any + Send + Sync> Component for T {}
+
+/// Maps from entities to their components.
+///
+/// Each component type gets its own `Column`, so all the positions (say) are stored next to each
+/// other rather than with the rest of their entity's components.  Columns are borrowed as a
+/// whole, so a system can hold on to several different columns at once, but not the same one
+/// mutably twice.
+pub struct EntityMap {
+    // Every live entity, so components can't be set on dead ones.
+    live: Column<()>,
+    // The newest generation of each entity index, to tell destroyed entities from made-up ones.
+    generations: Vec<u64>,
+    columns: HashMap<TypeId, ColumnCell>,
+}
+
+// A column, along with the name of its component type for errors.
+struct ColumnCell {
+    name: &'static str,
+    column: AtomicRefCell<Box<dyn AnyColumn>>,
+}
+
+impl ColumnCell {
+    fn try_borrow(&self) -> Result<AtomicRef<'_, Box<dyn AnyColumn>>, EcsError> {
+        self.column
+            .try_borrow()
+            .map_err(|_| self.already_borrowed())
+    }
+
+    fn try_borrow_mut(&self) -> Result<AtomicRefMut<'_, Box<dyn AnyColumn>>, EcsError> {
+        self.column
+            .try_borrow_mut()
+            .map_err(|_| self.already_borrowed())
+    }
 
-/// Maps from component type IDs to the corresponding component for a single entity.
-pub struct ComponentMap {
-    data: HashMap<TypeId, Box<Any>>,
+    fn already_borrowed(&self) -> EcsError {
+        EcsError::AlreadyBorrowed {
+            component: self.name,
+        }
+    }
 }
 
-impl ComponentMap {
+impl EntityMap {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
+            live: Column::new(),
+            generations: Vec::new(),
+            columns: HashMap::new(),
         }
     }
 
-    pub fn get<C: Component + Clone>(&self) -> C {
-        self.borrow::<C>().clone()
+    /// Like `get_opt`, but panics instead of failing, or if `entity` doesn't have a `C`.
+    pub fn get<C: Component + Clone>(&self, entity: &Entity) -> C {
+        self.borrow::<C>(entity).clone()
     }
 
-    pub fn borrow<C: Component>(&self) -> &C {
-        self.data
-            .get(&TypeId::of::<C>())
-            .map(|c| c.downcast_ref().unwrap())
+    /// Returns a copy of `entity`'s `C`, or `None` if it doesn't have one.
+    pub fn get_opt<C: Component + Clone>(&self, entity: &Entity) -> Result<Option<C>, EcsError> {
+        match self.try_borrow::<C>(entity) {
+            Ok(comp) => Ok(Some(comp.clone())),
+            Err(EcsError::MissingComponent { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Like `try_borrow`, but panics instead of failing.
+    pub fn borrow<C: Component>(&self, entity: &Entity) -> AtomicRef<'_, C> {
+        self.try_borrow(entity).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `try_borrow_mut`, but panics instead of failing.
+    pub fn borrow_mut<C: Component>(&self, entity: &Entity) -> AtomicRefMut<'_, C> {
+        self.try_borrow_mut(entity)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Borrows `entity`'s `C`.  Fails if it doesn't have one, or the column of `C`s is borrowed
+    /// mutably.
+    pub fn try_borrow<C: Component>(&self, entity: &Entity) -> Result<AtomicRef<'_, C>, EcsError> {
+        self.check_live(entity)?;
+        match self.try_column::<C>()? {
+            Some(column) if column.contains(entity) => {
+                Ok(AtomicRef::map(column, |c| c.get(entity).unwrap()))
+            }
+            _ => Err(missing_component::<C>(entity)),
+        }
+    }
+
+    /// Mutably borrows `entity`'s `C`.  Fails if it doesn't have one, or the column of `C`s is
+    /// already borrowed.
+    pub fn try_borrow_mut<C: Component>(
+        &self,
+        entity: &Entity,
+    ) -> Result<AtomicRefMut<'_, C>, EcsError> {
+        self.check_live(entity)?;
+        match self.try_column_mut::<C>()? {
+            Some(column) if column.contains(entity) => {
+                Ok(AtomicRefMut::map(column, |c| c.get_mut(entity).unwrap()))
+            }
+            _ => Err(missing_component::<C>(entity)),
+        }
+    }
+
+    /// Sets `entity`'s `C`, replacing the one it already had.  Returns false (and drops `comp`) if
+    /// `entity` isn't live.
+    pub fn set<C: Component>(&mut self, entity: &Entity, comp: C) -> bool {
+        if !self.is_live(entity) {
+            return false;
+        }
+        self.columns
+            .entry(TypeId::of::<C>())
+            .or_insert_with(|| ColumnCell {
+                name: type_name::<C>(),
+                column: AtomicRefCell::new(Box::new(Column::<C>::new())),
+            })
+            .column
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Column<C>>()
             .unwrap()
+            .insert(entity, comp);
+        true
     }
 
-    pub fn borrow_mut<C: Component>(&mut self) -> &mut C {
-        self.data
-            .get_mut(&TypeId::of::<C>())
-            .map(|c| c.downcast_mut().unwrap())
+    /// Removes `entity`'s `C`, returning it if it had one.
+    pub fn remove<C: Component>(&mut self, entity: &Entity) -> Option<C> {
+        self.columns
+            .get_mut(&TypeId::of::<C>())?
+            .column
+            .get_mut()
+            .as_any_mut()
+            .downcast_mut::<Column<C>>()
             .unwrap()
+            .remove(entity)
+    }
+
+    pub fn is_live(&self, entity: &Entity) -> bool {
+        self.live.contains(entity)
+    }
+
+    /// Fails with `NoSuchEntity` or `StaleGeneration` if `entity` isn't live.
+    pub fn check_live(&self, entity: &Entity) -> Result<(), EcsError> {
+        if self.is_live(entity) {
+            return Ok(());
+        }
+        match self.generations.get(entity.idx) {
+            Some(&gen) if entity.gen <= gen => Err(EcsError::StaleGeneration {
+                entity: entity.clone(),
+            }),
+            _ => Err(EcsError::NoSuchEntity {
+                entity: entity.clone(),
+            }),
+        }
+    }
+
+    /// Like `try_has`, but panics instead of failing.
+    pub fn has<C: Component>(&self, entity: &Entity) -> bool {
+        self.try_has::<C>(entity)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `try_has_type_id`, but panics instead of failing.
+    pub fn has_type_id(&self, entity: &Entity, type_id: &TypeId) -> bool {
+        self.try_has_type_id(entity, type_id)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Returns whether `entity` has a `C`.  Fails if the column of `C`s is borrowed mutably.
+    pub fn try_has<C: Component>(&self, entity: &Entity) -> Result<bool, EcsError> {
+        self.try_has_type_id(entity, &TypeId::of::<C>())
+    }
+
+    /// Returns whether `entity` has a component of type `type_id`.  Fails if its column is
+    /// borrowed mutably.
+    pub fn try_has_type_id(&self, entity: &Entity, type_id: &TypeId) -> Result<bool, EcsError> {
+        match self.columns.get(type_id) {
+            Some(column) => Ok(column.try_borrow()?.contains(entity)),
+            None => Ok(false),
+        }
+    }
+
+    /// Like `try_column`, but panics instead of failing.
+    pub fn column<C: Component>(&self) -> Option<AtomicRef<'_, Column<C>>> {
+        self.try_column().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `try_column_mut`, but panics instead of failing.
+    pub fn column_mut<C: Component>(&self) -> Option<AtomicRefMut<'_, Column<C>>> {
+        self.try_column_mut().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Borrows every `C` at once, or returns `None` if no entity has ever had one.  Fails if the
+    /// column is borrowed mutably.
+    pub fn try_column<C: Component>(&self) -> Result<Option<AtomicRef<'_, Column<C>>>, EcsError> {
+        let column = match self.columns.get(&TypeId::of::<C>()) {
+            Some(column) => column.try_borrow()?,
+            None => return Ok(None),
+        };
+        Ok(Some(AtomicRef::map(column, |c| {
+            c.as_any().downcast_ref().unwrap()
+        })))
+    }
+
+    /// Mutably borrows every `C` at once, or returns `None` if no entity has ever had one.  Fails
+    /// if the column is already borrowed.
+    pub fn try_column_mut<C: Component>(
+        &self,
+    ) -> Result<Option<AtomicRefMut<'_, Column<C>>>, EcsError> {
+        let column = match self.columns.get(&TypeId::of::<C>()) {
+            Some(column) => column.try_borrow_mut()?,
+            None => return Ok(None),
+        };
+        Ok(Some(AtomicRefMut::map(column, |c| {
+            c.as_any_mut().downcast_mut().unwrap()
+        })))
     }
 
-    pub fn set<C: Component>(&mut self, comp: C) {
-        self.data.insert(TypeId::of::<C>(), Box::new(comp));
+    /// Like `try_query`, but panics instead of failing.
+    pub fn query<Q: Fetch>(&self) -> Query<'_, Q> {
+        Query::new(self)
     }
 
-    pub fn remove<C: Component>(&mut self) {
-        self.data.remove(&TypeId::of::<C>());
+    /// Borrows the components `Q` asks for, from every entity that matches it.  Fails if `Q`
+    /// conflicts with a borrow that's already been made, or with itself.
+    pub fn try_query<Q: Fetch>(&self) -> Result<Query<'_, Q>, EcsError> {
+        Query::try_new(self)
     }
 
-    pub fn has<C: Component>(&self) -> bool {
-        self.has_type_id(&TypeId::of::<C>())
+    /// Every live entity.
+    pub fn entities(&self) -> &[Entity] {
+        self.live.entities()
     }
 
-    pub fn has_type_id(&self, type_id: &TypeId) -> bool {
-        self.data.contains_key(type_id)
+    /// Like `try_entities_with`, but panics instead of failing.
+    pub fn entities_with(&self, type_ids: &[TypeId]) -> Vec<Entity> {
+        self.try_entities_with(type_ids)
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Returns the entities that have all of the given component types.  Fails if any of their
+    /// columns are borrowed mutably.
+    pub fn try_entities_with(&self, type_ids: &[TypeId]) -> Result<Vec<Entity>, EcsError> {
+        if type_ids.is_empty() {
+            return Ok(self.live.entities().to_vec());
+        }
+        let mut columns = Vec::with_capacity(type_ids.len());
+        for type_id in type_ids {
+            match self.columns.get(type_id) {
+                Some(column) => columns.push(column.try_borrow()?),
+                None => return Ok(Vec::new()),
+            }
+        }
+        // Only the entities in the shortest column can possibly have everything.
+        columns.sort_by_key(|c| c.len());
+        let (shortest, rest) = columns.split_first().unwrap();
+        Ok(shortest
+            .entities()
+            .iter()
+            .filter(|e| rest.iter().all(|c| c.contains(e)))
+            .cloned()
+            .collect())
+    }
+
+    pub(crate) fn insert_entity(&mut self, entity: &Entity) {
+        if entity.idx >= self.generations.len() {
+            self.generations.resize(entity.idx + 1, 0);
+        }
+        self.generations[entity.idx] = entity.gen;
+        self.live.insert(entity, ());
+    }
+
+    /// Drops all of `entity`'s components.  Returns false if it wasn't live.
+    fn remove_entity(&mut self, entity: &Entity) -> bool {
+        if self.live.remove(entity).is_none() {
+            return false;
+        }
+        for column in self.columns.values_mut() {
+            column.column.get_mut().remove_entity(entity);
+        }
+        true
+    }
+}
+
+impl Default for EntityMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn missing_component<C: Component>(entity: &Entity) -> EcsError {
+    EcsError::MissingComponent {
+        entity: entity.clone(),
+        component: type_name::<C>(),
     }
 }
 
 pub struct Ecs<T> {
-    pub entity_map: GenerationalIndexArray<ComponentMap>,
-    // The order of the systems in the vec defines the order in which the systems will be run.
-    systems: Vec<Box<dyn System<T>>>,
-    entity_allocator: GenerationalIndexAllocator,
+    pub entity_map: EntityMap,
+    // Systems that conflict are run in the order they're in here, unless they're labelled
+    // otherwise.
+    systems: Vec<SystemDesc<T>>,
+    // Rebuilt from `systems` whenever one is added.
+    stages: Vec<Vec<usize>>,
+    // Locked so systems can create entities through their `Commands` while they run.
+    entity_allocator: Mutex<GenerationalIndexAllocator>,
     players: Vec<Entity>,
 }
 
 impl<T> Ecs<T> {
     pub fn new() -> Self {
         Self {
-            entity_map: GenerationalIndexArray::new(),
+            entity_map: EntityMap::new(),
             systems: Vec::new(),
-            entity_allocator: GenerationalIndexAllocator::new(),
+            stages: Vec::new(),
+            entity_allocator: Mutex::new(GenerationalIndexAllocator::new()),
             players: Vec::new(),
         }
     }
 
-    pub fn tick(&mut self, tick_config: &T) {
-        for system in self.systems.iter() {
-            // Find which components we need to filter on.
-            let comp_constraints = system.comp_constraints();
-            let filtered_entities: Vec<Entity> = self
-                .entity_allocator
-                .entries()
-                .filter(|e| {
-                    let comp_map = self.entity_map.borrow(e).unwrap();
-                    for comp_type_id in comp_constraints.iter() {
-                        if !comp_map.has_type_id(comp_type_id) {
-                            return false;
-                        }
-                    }
-                    true
-                })
-                .collect();
-            system.run(tick_config, &mut self.entity_map, &filtered_entities);
-        }
-    }
-
     pub fn create_entity(&mut self) -> Entity {
-        let result = self.entity_allocator.allocate();
-        // Initialize the entity's component map.
-        self.entity_map.set(&result, ComponentMap::new());
+        let result = self.entity_allocator.get_mut().unwrap().allocate();
+        self.entity_map.insert_entity(&result);
         result
     }
 
     /// Returns true if `entity` was successfully destroyed.  Returns false if `entity` was already
     /// destroyed.
     pub fn destroy_entity(&mut self, entity: Entity) -> bool {
-        let map_rm_success = self.entity_map.remove(&entity);
-        let alloc_rm_success = self.entity_allocator.deallocate(&entity);
-        // If the entity's been removed from one of these but not the other, we have problems.
-        assert_eq!(map_rm_success, alloc_rm_success);
-        map_rm_success
+        destroy(
+            &mut self.entity_map,
+            self.entity_allocator.get_mut().unwrap(),
+            &entity,
+        )
     }
 
-    pub fn systems(&mut self) -> &mut Vec<Box<dyn System<T>>> {
-        &mut self.systems
+    /// Adds `system` to the end of the schedule.  It can only be ordered against the labels of
+    /// systems that have already been added (or its own).  Fails, leaving the schedule as it was,
+    /// if its ordering can't be met.
+    pub fn add_system(&mut self, system: SystemDesc<T>) -> Result<(), EcsError> {
+        self.systems.push(system);
+        match schedule::build_stages(&self.systems) {
+            Ok(stages) => {
+                self.stages = stages;
+                Ok(())
+            }
+            Err(e) => {
+                self.systems.pop();
+                Err(e)
+            }
+        }
     }
 
     pub fn entities<'a>(&'a self) -> impl Iterator<Item = Entity> + 'a {
-        self.entity_allocator.entries()
+        self.entity_map.entities().iter().cloned()
+    }
+}
+
+fn destroy(
+    entity_map: &mut EntityMap,
+    entity_allocator: &mut GenerationalIndexAllocator,
+    entity: &Entity,
+) -> bool {
+    let map_rm_success = entity_map.remove_entity(entity);
+    let alloc_rm_success = entity_allocator.deallocate(entity);
+    // If the entity's been removed from one of these but not the other, we have problems.
+    assert_eq!(map_rm_success, alloc_rm_success);
+    map_rm_success
+}
+
+impl<T: Sync> Ecs<T> {
+    /// Runs every system once.  The systems in each stage (see `schedule::build_stages`) are
+    /// spread across rayon's thread pool, and the next stage starts once they've all finished.
+    /// In between, the `Commands` each system queued are applied, in the order the systems were
+    /// scheduled.
+    ///
+    /// A system that can't get at its components is skipped, and the rest still run.  Returns the
+    /// first such error.
+    pub fn tick(&mut self, tick_config: &T) -> Result<(), EcsError> {
+        let systems = &self.systems;
+        let entity_allocator = &self.entity_allocator;
+        let mut first_error = None;
+        for stage in self.stages.iter() {
+            let entity_map = &self.entity_map;
+            let mut runs: Vec<_> = stage
+                .iter()
+                .map(|&i| (i, Commands::new(entity_allocator), Ok(())))
+                .collect();
+            if let [(i, ref mut commands, ref mut result)] = runs[..] {
+                // Not worth handing off to another thread.
+                *result = systems[i].system.run_on(tick_config, entity_map, commands);
+            } else {
+                rayon::scope(|s| {
+                    for (i, commands, result) in runs.iter_mut() {
+                        let system = &systems[*i].system;
+                        s.spawn(move |_| {
+                            *result = system.run_on(tick_config, entity_map, commands)
+                        });
+                    }
+                });
+            }
+            for (_, commands, result) in runs {
+                if let Err(e) = result {
+                    first_error.get_or_insert(e);
+                }
+                commands.apply(&mut self.entity_map);
+            }
+        }
+        match first_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::query::Query;
+    use super::system::System;
+    use super::*;
+
+    #[test]
+    fn destroy_entity_drops_components() {
+        let mut ecs = Ecs::<()>::new();
+        let a = ecs.create_entity();
+        let b = ecs.create_entity();
+        assert!(ecs.entity_map.set(&a, 1u32));
+        assert!(ecs.entity_map.set(&b, 2u32));
+        assert!(ecs.entity_map.set(&b, "b"));
+
+        assert!(ecs.destroy_entity(b.clone()));
+        assert!(!ecs.destroy_entity(b.clone()));
+        assert!(!ecs.entity_map.has::<u32>(&b));
+        assert!(!ecs.entity_map.set(&b, 3u32));
+        assert_eq!(ecs.entity_map.query::<&u32>().len(), 1);
+        assert!(ecs.entity_map.query::<&&str>().is_empty());
+
+        // The reused slot starts out empty.
+        let c = ecs.create_entity();
+        assert_eq!(c.idx, b.idx);
+        assert!(!ecs.entity_map.has::<u32>(&c));
+        assert_eq!(ecs.entity_map.get::<u32>(&a), 1);
+    }
+
+    struct Counter(u32);
+    struct Total(u32);
+
+    // Adds `.0` to each entity's counter.
+    struct Count(u32);
+
+    impl System<u32> for Count {
+        type Query = &'static mut Counter;
+
+        fn run(&self, times: &u32, mut query: Query<Self::Query>, _: &mut Commands) {
+            query.for_each(|_, counter| counter.0 += self.0 * times);
+        }
+    }
+
+    struct Sum;
+
+    impl System<u32> for Sum {
+        type Query = (&'static mut Total, &'static Counter, &'static Vec<u32>);
+
+        fn run(&self, _: &u32, mut query: Query<Self::Query>, _: &mut Commands) {
+            query.for_each(|_, (total, counter, list)| {
+                total.0 = counter.0 + list.iter().sum::<u32>()
+            });
+        }
+    }
+
+    // Adds another 1 to each list.
+    struct Census;
+
+    impl System<u32> for Census {
+        type Query = (&'static mut Vec<u32>, Option<&'static Total>);
+
+        fn run(&self, _: &u32, mut query: Query<Self::Query>, _: &mut Commands) {
+            query.for_each(|_, (list, _)| list.push(1));
+        }
+    }
+
+    #[test]
+    fn tick_runs_stages_in_order() {
+        let mut ecs = Ecs::new();
+        ecs.add_system(SystemDesc::new(Count(1)).label("count"))
+            .unwrap();
+        ecs.add_system(SystemDesc::new(Count(10)).label("count"))
+            .unwrap();
+        ecs.add_system(SystemDesc::new(Sum).after("count")).unwrap();
+        ecs.add_system(SystemDesc::new(Census).before("count"))
+            .unwrap();
+        let entity = ecs.create_entity();
+        ecs.entity_map.set(&entity, Counter(0));
+        ecs.entity_map.set(&entity, Total(0));
+        ecs.entity_map.set(&entity, Vec::<u32>::new());
+
+        ecs.tick(&2).unwrap();
+        assert_eq!(ecs.entity_map.borrow::<Total>(&entity).0, 23);
+        ecs.tick(&1).unwrap();
+        assert_eq!(ecs.entity_map.borrow::<Total>(&entity).0, 35);
+    }
+
+    // Creates an entity, then panics before its commands can be applied.
+    struct Doomed;
+
+    impl System<u32> for Doomed {
+        type Query = &'static Counter;
+
+        fn run(&self, _: &u32, _: Query<Self::Query>, commands: &mut Commands) {
+            let entity = commands.create_entity();
+            commands.set(&entity, Counter(0));
+            panic!("doomed");
+        }
+    }
+
+    #[test]
+    fn unapplied_commands_free_their_entities() {
+        let mut ecs = Ecs::new();
+        ecs.add_system(SystemDesc::new(Doomed)).unwrap();
+        let a = ecs.create_entity();
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| ecs.tick(&1)));
+        assert!(result.is_err());
+        assert_eq!(ecs.entities().collect::<Vec<_>>(), vec![a.clone()]);
+        // The entity `Doomed` created never made it in, and its slot is free again.
+        let reserved = Entity {
+            idx: a.idx + 1,
+            gen: 0,
+        };
+        assert!(!ecs.destroy_entity(reserved.clone()));
+        let b = ecs.create_entity();
+        assert_eq!(b.idx, reserved.idx);
+        assert!(!ecs.entity_map.has::<Counter>(&b));
+    }
+
+    #[test]
+    fn add_system_rejects_bad_orderings() {
+        let mut ecs = Ecs::new();
+        // Labels have to be added before they're ordered against.
+        assert_eq!(
+            ecs.add_system(SystemDesc::new(Sum).after("count")),
+            Err(EcsError::UnknownLabel {
+                system: type_name::<Sum>(),
+                label: "count",
+            })
+        );
+        ecs.add_system(SystemDesc::new(Count(1)).label("count"))
+            .unwrap();
+        let count = type_name::<Count>();
+        assert_eq!(
+            ecs.add_system(SystemDesc::new(Count(10)).after("count").before("count")),
+            Err(EcsError::OrderCycle {
+                systems: vec![count, count],
+            })
+        );
+
+        // Neither of the rejected systems made it into the schedule.
+        let entity = ecs.create_entity();
+        ecs.entity_map.set(&entity, Counter(0));
+        ecs.tick(&1).unwrap();
+        assert_eq!(ecs.entity_map.borrow::<Counter>(&entity).0, 1);
+    }
+
+    #[test]
+    fn fallible_accessors() {
+        let mut ecs = Ecs::<()>::new();
+        let a = ecs.create_entity();
+        let b = ecs.create_entity();
+        ecs.entity_map.set(&a, 1u32);
+        ecs.destroy_entity(b.clone());
+        let map = &ecs.entity_map;
+
+        assert_eq!(map.get_opt::<u32>(&a), Ok(Some(1)));
+        assert_eq!(map.get_opt::<u8>(&a), Ok(None));
+        assert_eq!(
+            map.try_borrow::<u8>(&a).err(),
+            Some(EcsError::MissingComponent {
+                entity: a.clone(),
+                component: "u8",
+            })
+        );
+        assert_eq!(
+            map.get_opt::<u32>(&b),
+            Err(EcsError::StaleGeneration { entity: b.clone() })
+        );
+        let made_up = Entity { idx: 7, gen: 0 };
+        assert_eq!(
+            map.try_borrow_mut::<u32>(&made_up).err(),
+            Some(EcsError::NoSuchEntity { entity: made_up })
+        );
+
+        let borrowed = map.borrow_mut::<u32>(&a);
+        let already_borrowed = Some(EcsError::AlreadyBorrowed { component: "u32" });
+        assert_eq!(map.try_borrow::<u32>(&a).err(), already_borrowed);
+        assert_eq!(map.try_query::<&u32>().err(), already_borrowed);
+        assert_eq!(map.try_has::<u32>(&a).err(), already_borrowed);
+        assert_eq!(
+            map.try_entities_with(&[TypeId::of::<u32>()]).err(),
+            already_borrowed
+        );
+        drop(borrowed);
+        assert_eq!(map.try_has::<u32>(&a), Ok(true));
+        assert_eq!(
+            map.try_query::<(&mut u32, Option<&u32>)>().err(),
+            already_borrowed
+        );
+    }
+
+    struct Conflicted;
+
+    impl System<u32> for Conflicted {
+        type Query = (&'static mut Counter, &'static Counter);
+
+        fn run(&self, _: &u32, _: Query<Self::Query>, _: &mut Commands) {
+            panic!("shouldn't have been able to borrow its query");
+        }
+    }
+
+    #[test]
+    fn tick_skips_systems_that_fail() {
+        let mut ecs = Ecs::new();
+        ecs.add_system(SystemDesc::new(Conflicted)).unwrap();
+        ecs.add_system(SystemDesc::new(Count(1))).unwrap();
+        let entity = ecs.create_entity();
+        ecs.entity_map.set(&entity, Counter(0));
+
+        let component = type_name::<Counter>();
+        assert_eq!(ecs.tick(&1), Err(EcsError::AlreadyBorrowed { component }));
+        assert_eq!(ecs.entity_map.borrow::<Counter>(&entity).0, 1);
+    }
+
+    // Gives each entity whose counter is above 0 a child with one less (taking away its total),
+    // and destroys the rest.
+    struct Spawn;
+
+    impl System<u32> for Spawn {
+        type Query = (&'static Counter, Option<&'static Total>);
+
+        fn run(&self, _: &u32, mut query: Query<Self::Query>, commands: &mut Commands) {
+            query.for_each(|entity, (counter, total)| {
+                if counter.0 == 0 {
+                    commands.destroy_entity(entity.clone());
+                    return;
+                }
+                let child = commands.create_entity();
+                commands.set(&child, Counter(counter.0 - 1));
+                if total.is_some() {
+                    commands.remove::<Total>(entity);
+                }
+            });
+        }
+    }
+
+    #[test]
+    fn commands_apply_between_stages() {
+        let mut ecs = Ecs::new();
+        ecs.add_system(SystemDesc::new(Spawn)).unwrap();
+        ecs.add_system(SystemDesc::new(Count(10))).unwrap();
+        let a = ecs.create_entity();
+        let b = ecs.create_entity();
+        ecs.entity_map.set(&a, Counter(1));
+        ecs.entity_map.set(&a, Total(0));
+        ecs.entity_map.set(&b, Counter(0));
+
+        ecs.tick(&1).unwrap();
+        assert!(!ecs.entity_map.is_live(&b));
+        assert!(!ecs.entity_map.has::<Total>(&a));
+        assert_eq!(ecs.entity_map.borrow::<Counter>(&a).0, 11);
+        // `Count` ran after the child was created.
+        let child = ecs.entities().find(|e| *e != a).unwrap();
+        assert_eq!(ecs.entity_map.borrow::<Counter>(&child).0, 10);
+        assert_eq!(ecs.entities().count(), 2);
+
+        // Both are above 0 now, so each gets a child.
+        ecs.tick(&1).unwrap();
+        assert_eq!(ecs.entities().count(), 4);
     }
 }
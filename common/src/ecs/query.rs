@@ -0,0 +1,399 @@
+use std::any::TypeId;
+use std::marker::PhantomData;
+
+use atomic_refcell::{AtomicRef, AtomicRefMut};
+
+use super::error::EcsError;
+use super::storage::Column;
+use super::{Component, Entity, EntityMap};
+
+/// Which component types a query looks at, and how.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    /// Components that are borrowed immutably.
+    pub reads: Vec<TypeId>,
+    /// Components that are borrowed mutably.
+    pub writes: Vec<TypeId>,
+    /// Components that every matching entity has.
+    pub with: Vec<TypeId>,
+    /// Components that no matching entity has.
+    pub without: Vec<TypeId>,
+}
+
+impl Access {
+    /// Returns true if one side writes a component that the other reads or writes, so they can't
+    /// run at the same time.
+    pub fn conflicts_with(&self, other: &Access) -> bool {
+        let overlaps = |writes: &[TypeId], other: &Access| {
+            writes
+                .iter()
+                .any(|t| other.reads.contains(t) || other.writes.contains(t))
+        };
+        overlaps(&self.writes, other) || overlaps(&other.writes, self)
+    }
+}
+
+/// The part of a query that picks out one component (or filter), or a tuple of them.
+///
+/// This is implemented for:
+///
+/// - `&C` and `&mut C`, which only match entities with a `C`;
+/// - `Option<&C>` and `Option<&mut C>`, which match everything, and give `None` for the entities
+///   without a `C`;
+/// - `Without<C>`, which only matches entities without a `C`, and gives `()`;
+/// - tuples of up to eight of the above.
+pub trait Fetch {
+    /// What's handed out for each matching entity.
+    type Item<'a>;
+    /// The columns borrowed while the query is running.
+    type Borrow<'w>;
+
+    /// Adds the components this looks at to `access`.
+    fn access(access: &mut Access);
+
+    /// Borrows the columns from `entity_map`.  Returns `None` if nothing can match, because a
+    /// required column doesn't exist.
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError>;
+
+    /// Returns the shortest list of entities that everything matching has to be in, or `None` if
+    /// anything could match.
+    fn narrowest<'b>(borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]>;
+
+    fn matches(borrow: &Self::Borrow<'_>, entity: &Entity) -> bool;
+
+    /// Gets `entity`'s item.  Panics if it doesn't match.
+    fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a>;
+}
+
+/// Filters out the entities that have a `C`.
+pub struct Without<C>(PhantomData<C>);
+
+impl<C: Component> Fetch for &C {
+    type Item<'a> = &'a C;
+    type Borrow<'w> = AtomicRef<'w, Column<C>>;
+
+    fn access(access: &mut Access) {
+        access.reads.push(TypeId::of::<C>());
+        access.with.push(TypeId::of::<C>());
+    }
+
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+        entity_map.try_column::<C>()
+    }
+
+    fn narrowest<'b>(borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+        Some(borrow.entities())
+    }
+
+    fn matches(borrow: &Self::Borrow<'_>, entity: &Entity) -> bool {
+        borrow.contains(entity)
+    }
+
+    fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a> {
+        borrow.get(entity).unwrap()
+    }
+}
+
+impl<C: Component> Fetch for &mut C {
+    type Item<'a> = &'a mut C;
+    type Borrow<'w> = AtomicRefMut<'w, Column<C>>;
+
+    fn access(access: &mut Access) {
+        access.writes.push(TypeId::of::<C>());
+        access.with.push(TypeId::of::<C>());
+    }
+
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+        entity_map.try_column_mut::<C>()
+    }
+
+    fn narrowest<'b>(borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+        Some(borrow.entities())
+    }
+
+    fn matches(borrow: &Self::Borrow<'_>, entity: &Entity) -> bool {
+        borrow.contains(entity)
+    }
+
+    fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a> {
+        borrow.get_mut(entity).unwrap()
+    }
+}
+
+impl<C: Component> Fetch for Option<&C> {
+    type Item<'a> = Option<&'a C>;
+    type Borrow<'w> = Option<AtomicRef<'w, Column<C>>>;
+
+    fn access(access: &mut Access) {
+        access.reads.push(TypeId::of::<C>());
+    }
+
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+        Ok(Some(entity_map.try_column::<C>()?))
+    }
+
+    fn narrowest<'b>(_borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+        None
+    }
+
+    fn matches(_borrow: &Self::Borrow<'_>, _entity: &Entity) -> bool {
+        true
+    }
+
+    fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a> {
+        borrow.as_ref().and_then(|c| c.get(entity))
+    }
+}
+
+impl<C: Component> Fetch for Option<&mut C> {
+    type Item<'a> = Option<&'a mut C>;
+    type Borrow<'w> = Option<AtomicRefMut<'w, Column<C>>>;
+
+    fn access(access: &mut Access) {
+        access.writes.push(TypeId::of::<C>());
+    }
+
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+        Ok(Some(entity_map.try_column_mut::<C>()?))
+    }
+
+    fn narrowest<'b>(_borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+        None
+    }
+
+    fn matches(_borrow: &Self::Borrow<'_>, _entity: &Entity) -> bool {
+        true
+    }
+
+    fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a> {
+        borrow.as_mut().and_then(|c| c.get_mut(entity))
+    }
+}
+
+// Checking for a component still means looking at its column, so this counts as a read.
+impl<C: Component> Fetch for Without<C> {
+    type Item<'a> = ();
+    type Borrow<'w> = Option<AtomicRef<'w, Column<C>>>;
+
+    fn access(access: &mut Access) {
+        access.reads.push(TypeId::of::<C>());
+        access.without.push(TypeId::of::<C>());
+    }
+
+    fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+        Ok(Some(entity_map.try_column::<C>()?))
+    }
+
+    fn narrowest<'b>(_borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+        None
+    }
+
+    fn matches(borrow: &Self::Borrow<'_>, entity: &Entity) -> bool {
+        match borrow {
+            Some(c) => !c.contains(entity),
+            None => true,
+        }
+    }
+
+    fn fetch<'a>(_borrow: &'a mut Self::Borrow<'_>, _entity: &Entity) -> Self::Item<'a> {}
+}
+
+macro_rules! impl_fetch_tuple {
+    ( $( $name: ident $idx: tt ),* ) => {
+        impl<$( $name: Fetch ),*> Fetch for ( $( $name, )* ) {
+            type Item<'a> = ( $( $name::Item<'a>, )* );
+            type Borrow<'w> = ( $( $name::Borrow<'w>, )* );
+
+            fn access(access: &mut Access) {
+                $( $name::access(access); )*
+            }
+
+            fn borrow(entity_map: &EntityMap) -> Result<Option<Self::Borrow<'_>>, EcsError> {
+                Ok(Some(( $( match $name::borrow(entity_map)? {
+                    Some(borrow) => borrow,
+                    None => return Ok(None),
+                }, )* )))
+            }
+
+            fn narrowest<'b>(borrow: &'b Self::Borrow<'_>) -> Option<&'b [Entity]> {
+                let mut result: Option<&[Entity]> = None;
+                $(
+                    if let Some(entities) = $name::narrowest(&borrow.$idx) {
+                        if result.map_or(true, |r| entities.len() < r.len()) {
+                            result = Some(entities);
+                        }
+                    }
+                )*
+                result
+            }
+
+            fn matches(borrow: &Self::Borrow<'_>, entity: &Entity) -> bool {
+                $( $name::matches(&borrow.$idx, entity) )&&*
+            }
+
+            fn fetch<'a>(borrow: &'a mut Self::Borrow<'_>, entity: &Entity) -> Self::Item<'a> {
+                ( $( $name::fetch(&mut borrow.$idx, entity), )* )
+            }
+        }
+    };
+}
+
+impl_fetch_tuple!(A 0);
+impl_fetch_tuple!(A 0, B 1);
+impl_fetch_tuple!(A 0, B 1, C 2);
+impl_fetch_tuple!(A 0, B 1, C 2, D 3);
+impl_fetch_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_fetch_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+impl_fetch_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+impl_fetch_tuple!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+/// The entities that match `Q`, along with their components.
+///
+/// The columns `Q` looks at stay borrowed until the query is dropped, so a `&mut C` query can't
+/// be run at the same time as anything else that looks at `C`s.
+pub struct Query<'w, Q: Fetch> {
+    entity_map: &'w EntityMap,
+    borrow: Option<Q::Borrow<'w>>,
+    entities: Vec<Entity>,
+}
+
+impl<'w, Q: Fetch> Query<'w, Q> {
+    /// Like `try_new`, but panics instead of failing.
+    pub fn new(entity_map: &'w EntityMap) -> Self {
+        Self::try_new(entity_map).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Fails if one of the columns `Q` looks at is already borrowed mutably, or if `Q` borrows
+    /// one mutably that's already borrowed at all (including by `Q` itself).
+    pub fn try_new(entity_map: &'w EntityMap) -> Result<Self, EcsError> {
+        let borrow = Q::borrow(entity_map)?;
+        let entities = match borrow {
+            Some(ref b) => Q::narrowest(b)
+                .unwrap_or_else(|| entity_map.entities())
+                .iter()
+                .filter(|e| Q::matches(b, e))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        Ok(Self {
+            entity_map,
+            borrow,
+            entities,
+        })
+    }
+
+    /// Returns the component types `Q` looks at.
+    pub fn access() -> Access {
+        let mut result = Access::default();
+        Q::access(&mut result);
+        result
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// The entities that match.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Gets `entity`'s item, or `None` if it doesn't match.
+    pub fn get(&mut self, entity: &Entity) -> Option<Q::Item<'_>> {
+        let borrow = self.borrow.as_mut()?;
+        if self.entity_map.is_live(entity) && Q::matches(borrow, entity) {
+            Some(Q::fetch(borrow, entity))
+        } else {
+            None
+        }
+    }
+
+    /// Calls `f` with each matching entity and its item.
+    pub fn for_each<F: FnMut(&Entity, Q::Item<'_>)>(&mut self, mut f: F) {
+        if let Some(ref mut borrow) = self.borrow {
+            for entity in self.entities.iter() {
+                f(entity, Q::fetch(borrow, entity));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::Ecs;
+
+    #[derive(Debug, PartialEq)]
+    struct Pos(i32);
+    #[derive(Debug, PartialEq)]
+    struct Vel(i32);
+    struct Frozen;
+
+    // Three entities with positions, two of which move, one of which is frozen.
+    fn ecs() -> (Ecs<()>, Vec<Entity>) {
+        let mut ecs = Ecs::new();
+        let entities: Vec<_> = (0..3).map(|_| ecs.create_entity()).collect();
+        for (i, e) in entities.iter().enumerate() {
+            ecs.entity_map.set(e, Pos(0));
+            if i > 0 {
+                ecs.entity_map.set(e, Vel(i as i32));
+            }
+        }
+        ecs.entity_map.set(&entities[2], Frozen);
+        (ecs, entities)
+    }
+
+    #[test]
+    fn required_and_excluded() {
+        let (ecs, entities) = ecs();
+        let mut query = ecs.entity_map.query::<(&mut Pos, &Vel, Without<Frozen>)>();
+        assert_eq!(query.entities(), &entities[1..2]);
+        query.for_each(|_, (pos, vel, ())| pos.0 += vel.0);
+        drop(query);
+
+        let positions: Vec<_> = entities
+            .iter()
+            .map(|e| ecs.entity_map.borrow::<Pos>(e).0)
+            .collect();
+        assert_eq!(positions, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn optional() {
+        let (ecs, entities) = ecs();
+        let mut query = ecs.entity_map.query::<(&Pos, Option<&Vel>)>();
+        assert_eq!(query.len(), 3);
+        let mut vels = Vec::new();
+        query.for_each(|_, (_, vel)| vels.push(vel.map(|v| v.0)));
+        assert_eq!(vels, vec![None, Some(1), Some(2)]);
+
+        assert_eq!(query.get(&entities[1]), Some((&Pos(0), Some(&Vel(1)))));
+    }
+
+    #[test]
+    fn missing_columns() {
+        let (mut ecs, entities) = ecs();
+        assert!(ecs.entity_map.query::<&String>().is_empty());
+        assert_eq!(ecs.entity_map.query::<Option<&String>>().len(), 3);
+        assert_eq!(ecs.entity_map.query::<Without<String>>().len(), 3);
+
+        ecs.destroy_entity(entities[0].clone());
+        let mut query = ecs.entity_map.query::<Option<&Pos>>();
+        assert_eq!(query.len(), 2);
+        assert!(query.get(&entities[0]).is_none());
+    }
+
+    #[test]
+    fn access() {
+        let access = Query::<(&mut Pos, &Vel, Option<&u8>, Without<Frozen>)>::access();
+        assert_eq!(access.writes, type_id_vec![Pos]);
+        assert_eq!(access.reads, type_id_vec![Vel, u8, Frozen]);
+        assert_eq!(access.with, type_id_vec![Pos, Vel]);
+        assert_eq!(access.without, type_id_vec![Frozen]);
+    }
+}
@@ -0,0 +1,162 @@
+//! Splits systems into stages that can each be run in parallel.
+
+use std::collections::{BTreeSet, HashMap};
+
+use super::error::EcsError;
+use super::system::SystemDesc;
+
+/// Returns the indices of `systems`, grouped into stages.  The systems in a stage don't conflict
+/// with each other, and every stage only has to wait for the ones before it.
+///
+/// Systems are first sorted by their `before`/`after` constraints, keeping the order they were
+/// added in wherever the constraints allow.  Each then goes in the earliest stage that's after
+/// everything it's ordered after or conflicts with.
+///
+/// Fails if the constraints name a label no system has, or go around in a circle.
+pub fn build_stages<T>(systems: &[SystemDesc<T>]) -> Result<Vec<Vec<usize>>, EcsError> {
+    let mut labelled: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, system) in systems.iter().enumerate() {
+        for label in system.labels.iter() {
+            labelled.entry(label).or_default().push(i);
+        }
+    }
+    let find = |label: &'static str, system: &SystemDesc<T>| match labelled.get(label) {
+        Some(found) => Ok(found.clone()),
+        None => Err(EcsError::UnknownLabel {
+            system: system.name,
+            label,
+        }),
+    };
+
+    // `preds[i]` is everything that has to run before system `i`.
+    let mut preds = vec![BTreeSet::new(); systems.len()];
+    for (i, system) in systems.iter().enumerate() {
+        for &label in system.after.iter() {
+            preds[i].extend(find(label, system)?.into_iter().filter(|&j| j != i));
+        }
+        for &label in system.before.iter() {
+            for j in find(label, system)?.into_iter().filter(|&j| j != i) {
+                preds[j].insert(i);
+            }
+        }
+    }
+
+    // Sort topologically, always taking the earliest-added system that's ready.
+    let mut waiting_on: Vec<usize> = preds.iter().map(|p| p.len()).collect();
+    let mut ready: BTreeSet<usize> = (0..systems.len()).filter(|&i| waiting_on[i] == 0).collect();
+    let mut order = Vec::with_capacity(systems.len());
+    while let Some(&i) = ready.iter().next() {
+        ready.remove(&i);
+        order.push(i);
+        for (j, p) in preds.iter().enumerate() {
+            if p.contains(&i) {
+                waiting_on[j] -= 1;
+                if waiting_on[j] == 0 {
+                    ready.insert(j);
+                }
+            }
+        }
+    }
+    if order.len() < systems.len() {
+        let stuck = (0..systems.len())
+            .filter(|&i| waiting_on[i] > 0)
+            .map(|i| systems[i].name)
+            .collect();
+        return Err(EcsError::OrderCycle { systems: stuck });
+    }
+
+    let mut stage_of = vec![0; systems.len()];
+    let mut stages: Vec<Vec<usize>> = Vec::new();
+    for (n, &i) in order.iter().enumerate() {
+        for &j in order[..n].iter() {
+            if preds[i].contains(&j) || systems[i].access.conflicts_with(&systems[j].access) {
+                stage_of[i] = stage_of[i].max(stage_of[j] + 1);
+            }
+        }
+        if stage_of[i] == stages.len() {
+            stages.push(Vec::new());
+        }
+        stages[stage_of[i]].push(i);
+    }
+    Ok(stages)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::any::type_name;
+    use std::marker::PhantomData;
+
+    use super::*;
+    use crate::ecs::commands::Commands;
+    use crate::ecs::query::{Fetch, Query, Without};
+    use crate::ecs::system::System;
+
+    struct A;
+    struct B;
+
+    // Just enough to look at some components.
+    struct Reads<Q>(PhantomData<Q>);
+
+    impl<Q: Fetch + Send + Sync> System<()> for Reads<Q> {
+        type Query = Q;
+
+        fn run(&self, _: &(), _: Query<Q>, _: &mut Commands) {}
+    }
+
+    fn desc<Q: Fetch + Send + Sync + 'static>() -> SystemDesc<()> {
+        SystemDesc::new(Reads::<Q>(PhantomData))
+    }
+
+    #[test]
+    fn conflicts_keep_their_order() {
+        let systems = vec![
+            desc::<&'static mut A>(),
+            desc::<&'static B>(),
+            desc::<&'static A>(),
+            desc::<(&'static mut B, Without<A>)>(),
+            desc::<&'static B>(),
+        ];
+        assert_eq!(
+            build_stages(&systems),
+            Ok(vec![vec![0, 1], vec![2, 3], vec![4]])
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let systems = vec![
+            desc::<&'static A>().after("b"),
+            desc::<&'static B>().label("b"),
+            desc::<&'static B>().before("a").label("b"),
+            desc::<&'static A>().label("a"),
+        ];
+        assert_eq!(build_stages(&systems), Ok(vec![vec![1, 2], vec![0, 3]]));
+    }
+
+    #[test]
+    fn cycle() {
+        let systems = vec![
+            desc::<&'static B>(),
+            desc::<&'static A>().label("x").after("y"),
+            desc::<&'static A>().label("y").after("x"),
+        ];
+        let name = type_name::<Reads<&'static A>>();
+        assert_eq!(
+            build_stages(&systems),
+            Err(EcsError::OrderCycle {
+                systems: vec![name, name],
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_label() {
+        assert_eq!(
+            build_stages(&[desc::<&'static A>().before("nope")]),
+            Err(EcsError::UnknownLabel {
+                system: type_name::<Reads<&'static A>>(),
+                label: "nope",
+            })
+        );
+    }
+}
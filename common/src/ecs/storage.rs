@@ -0,0 +1,210 @@
+use std::any::Any;
+
+use super::{Component, Entity};
+
+/// Dense storage for every instance of one component type, as a sparse set.
+///
+/// The components are packed together in `data`, so systems can run through them without chasing
+/// pointers.  `sparse` maps from entity indices to positions in `data`, and `entities` maps back the
+/// other way, so lookups, inserts and removals are all constant time.  Removals swap the last
+/// component into the hole, so the order of the components isn't stable.
+pub struct Column<C> {
+    sparse: Vec<Option<usize>>,
+    entities: Vec<Entity>,
+    data: Vec<C>,
+}
+
+impl<C> Column<C> {
+    pub fn new() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            data: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn contains(&self, entity: &Entity) -> bool {
+        self.dense_idx(entity).is_some()
+    }
+
+    pub fn get(&self, entity: &Entity) -> Option<&C> {
+        self.dense_idx(entity).map(move |i| &self.data[i])
+    }
+
+    pub fn get_mut(&mut self, entity: &Entity) -> Option<&mut C> {
+        match self.dense_idx(entity) {
+            Some(i) => Some(&mut self.data[i]),
+            None => None,
+        }
+    }
+
+    /// Sets `entity`'s component, replacing the one it already had (if any).
+    pub fn insert(&mut self, entity: &Entity, comp: C) {
+        if let Some(i) = self.dense_idx(entity) {
+            self.data[i] = comp;
+            return;
+        }
+        if entity.idx >= self.sparse.len() {
+            self.sparse.resize(entity.idx + 1, None);
+        }
+        // An older generation of the entity could still be here if it was never removed, in which
+        // case it's overwritten.
+        if let Some(i) = self.sparse[entity.idx] {
+            self.entities[i] = entity.clone();
+            self.data[i] = comp;
+            return;
+        }
+        self.sparse[entity.idx] = Some(self.data.len());
+        self.entities.push(entity.clone());
+        self.data.push(comp);
+    }
+
+    /// Removes `entity`'s component, returning it if it had one.
+    pub fn remove(&mut self, entity: &Entity) -> Option<C> {
+        let i = self.dense_idx(entity)?;
+        self.sparse[entity.idx] = None;
+        self.entities.swap_remove(i);
+        let result = self.data.swap_remove(i);
+        // Point the entity that was moved into the hole at its new position.
+        if let Some(moved) = self.entities.get(i) {
+            self.sparse[moved.idx] = Some(i);
+        }
+        Some(result)
+    }
+
+    /// The entities that have this component, in the same order as `iter`.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&Entity, &C)> {
+        self.entities.iter().zip(self.data.iter())
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&Entity, &mut C)> {
+        self.entities.iter().zip(self.data.iter_mut())
+    }
+
+    fn dense_idx(&self, entity: &Entity) -> Option<usize> {
+        match self.sparse.get(entity.idx) {
+            Some(&Some(i)) if self.entities[i].gen == entity.gen => Some(i),
+            _ => None,
+        }
+    }
+}
+
+impl<C> Default for Column<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The parts of a `Column` that don't depend on its component type, so columns of every type can
+/// be kept together.
+pub trait AnyColumn: Any + Send + Sync {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, entity: &Entity) -> bool;
+
+    fn entities(&self) -> &[Entity];
+
+    /// Drops `entity`'s component, if it had one.
+    fn remove_entity(&mut self, entity: &Entity);
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<C: Component> AnyColumn for Column<C> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn contains(&self, entity: &Entity) -> bool {
+        self.contains(entity)
+    }
+
+    fn entities(&self) -> &[Entity] {
+        self.entities()
+    }
+
+    fn remove_entity(&mut self, entity: &Entity) {
+        self.remove(entity);
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(idx: usize, gen: u64) -> Entity {
+        Entity { idx, gen }
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let mut column = Column::new();
+        column.insert(&entity(3, 0), "three");
+        column.insert(&entity(0, 0), "zero");
+        assert_eq!(column.len(), 2);
+        assert_eq!(column.get(&entity(3, 0)), Some(&"three"));
+        assert_eq!(column.get(&entity(0, 0)), Some(&"zero"));
+        assert_eq!(column.get(&entity(1, 0)), None);
+        assert_eq!(column.get(&entity(7, 0)), None);
+
+        column.insert(&entity(3, 0), "THREE");
+        assert_eq!(column.len(), 2);
+        assert_eq!(column.get(&entity(3, 0)), Some(&"THREE"));
+    }
+
+    #[test]
+    fn remove_keeps_the_rest_reachable() {
+        let mut column = Column::new();
+        for i in 0..4 {
+            column.insert(&entity(i, 0), i);
+        }
+        assert_eq!(column.remove(&entity(1, 0)), Some(1));
+        assert_eq!(column.remove(&entity(1, 0)), None);
+        assert_eq!(column.len(), 3);
+        for &i in &[0, 2, 3] {
+            assert_eq!(column.get(&entity(i, 0)), Some(&i));
+        }
+        assert_eq!(column.entities().len(), 3);
+        assert!(column.iter().all(|(e, &i)| e.idx == i));
+    }
+
+    #[test]
+    fn stale_generations_miss() {
+        let mut column = Column::new();
+        column.insert(&entity(2, 5), 'a');
+        assert_eq!(column.get(&entity(2, 4)), None);
+        assert_eq!(column.remove(&entity(2, 4)), None);
+        assert!(!column.contains(&entity(2, 6)));
+
+        column.insert(&entity(2, 6), 'b');
+        assert_eq!(column.len(), 1);
+        assert_eq!(column.get(&entity(2, 5)), None);
+        assert_eq!(column.get(&entity(2, 6)), Some(&'b'));
+    }
+}
@@ -1,8 +1,123 @@
-use std::any::TypeId;
+use std::any::type_name;
 
-use super::{Entity, EntityMap};
+use super::commands::Commands;
+use super::error::EcsError;
+use super::query::{Access, Fetch, Query};
+use super::EntityMap;
 
-pub trait System<T> {
-    fn comp_constraints(&self) -> Vec<TypeId>;
-    fn run(&self, tick_config: &T, entity_map: &mut EntityMap, entities: &Vec<Entity>);
+/// Something that runs over every entity matching its `Query` once a tick.
+///
+/// Systems whose queries don't conflict (see `Access::conflicts_with`) can be run at the same time
+/// on different threads, so they have to be `Send` and `Sync`.  Anything that would affect other
+/// systems' queries, like creating or destroying entities, goes through `commands` instead.
+///
+/// Example:
+/// ```ignore
+/// impl System<TickConfig> for GravitySystem {
+///     type Query = (&'static mut PositionComponent, Without<FlyingComponent>);
+///
+///     fn run(
+///         &self,
+///         tick_config: &TickConfig,
+///         mut query: Query<Self::Query>,
+///         commands: &mut Commands,
+///     ) {
+///         query.for_each(|entity, (pos, ())| {
+///             pos.y -= GRAVITY * tick_config.dt;
+///             if pos.y < 0.0 {
+///                 commands.destroy_entity(entity.clone());
+///             }
+///         });
+///     }
+/// }
+/// ```
+pub trait System<T>: Send + Sync {
+    /// The components the system works on.  The lifetimes in it don't matter, so they're
+    /// written as `'static`.
+    type Query: Fetch;
+
+    fn run(&self, tick_config: &T, query: Query<Self::Query>, commands: &mut Commands);
+}
+
+/// The parts of a `System` that don't depend on its query type, so systems with different queries
+/// can be kept together.
+pub trait AnySystem<T>: Send + Sync {
+    /// The components the system reads and writes.
+    fn access(&self) -> Access;
+
+    /// Queries `entity_map` and runs the system on the result.  Fails without running the system
+    /// if the query does.
+    fn run_on(
+        &self,
+        tick_config: &T,
+        entity_map: &EntityMap,
+        commands: &mut Commands,
+    ) -> Result<(), EcsError>;
+}
+
+impl<T, S: System<T>> AnySystem<T> for S {
+    fn access(&self) -> Access {
+        Query::<S::Query>::access()
+    }
+
+    fn run_on(
+        &self,
+        tick_config: &T,
+        entity_map: &EntityMap,
+        commands: &mut Commands,
+    ) -> Result<(), EcsError> {
+        self.run(tick_config, Query::try_new(entity_map)?, commands);
+        Ok(())
+    }
+}
+
+/// A system, along with where it goes in the schedule.
+///
+/// By default, systems that conflict run in the order they were added, and the rest are free to
+/// run alongside them.  Labels let systems be ordered by name instead.
+///
+/// Example:
+/// ```ignore
+/// ecs.add_system(SystemDesc::new(PowerSystem).label("power"))?;
+/// ecs.add_system(SystemDesc::new(LightingSystem).after("power"))?;
+/// ```
+pub struct SystemDesc<T> {
+    pub(crate) system: Box<dyn AnySystem<T>>,
+    pub(crate) name: &'static str,
+    pub(crate) access: Access,
+    pub(crate) labels: Vec<&'static str>,
+    pub(crate) before: Vec<&'static str>,
+    pub(crate) after: Vec<&'static str>,
+}
+
+impl<T> SystemDesc<T> {
+    pub fn new<S: System<T> + 'static>(system: S) -> Self {
+        Self {
+            access: system.access(),
+            system: Box::new(system),
+            name: type_name::<S>(),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+        }
+    }
+
+    /// Gives the system a name that others can be ordered against.  More than one system can have
+    /// the same label.
+    pub fn label(mut self, label: &'static str) -> Self {
+        self.labels.push(label);
+        self
+    }
+
+    /// Runs the system before every system labelled `label`.
+    pub fn before(mut self, label: &'static str) -> Self {
+        self.before.push(label);
+        self
+    }
+
+    /// Runs the system after every system labelled `label`.
+    pub fn after(mut self, label: &'static str) -> Self {
+        self.after.push(label);
+        self
+    }
 }
@@ -1,7 +1,9 @@
+extern crate atomic_refcell;
 extern crate glutin_window;
 extern crate graphics;
 extern crate opengl_graphics;
 extern crate piston;
+extern crate rayon;
 
 extern crate serde;
 #[macro_use]
@@ -14,7 +14,7 @@ macro_rules! type_id_vec {
     ($($x:ty,)*) => (type_id_vec![$($x),*])
 }
 
-/// For defining a list of boxed systems by name.
+/// For defining a list of systems by name, in the order they're added to the schedule.
 ///
 /// Example:
 /// ```ignore
@@ -26,7 +26,7 @@ macro_rules! type_id_vec {
 #[macro_export]
 macro_rules! sys_vec {
     ($($x:ident),*) => (
-        vec![$(Box::new($x {})),*]
+        vec![$($crate::ecs::system::SystemDesc::new($x {})),*]
     );
     ($($x:ident,)*) => (sys_vec![$($x),*])
 }
@@ -2,6 +2,10 @@ pub mod socket;
 
 use std::net::{Ipv4Addr, SocketAddrV4};
 
+use serde::Schema;
+
+use self::packet::Packet;
+
 // TODO: Once const functions are in stable, we should make these SocketAddrV4's.
 pub const BIND_ADDR: [u8; 4] = [127, 0, 0, 1];
 pub const SERVER_PORT: u16 = 7878;
@@ -11,24 +15,46 @@ pub fn to_socket_addr(addr: [u8; 4], port: u16) -> SocketAddrV4 {
     SocketAddrV4::new(Ipv4Addr::from(addr), port)
 }
 
+/// Identifies the wire format of every packet (and everything inside them).  A client and server
+/// can only talk to each other if they agree on this.
+pub fn protocol_fingerprint() -> u64 {
+    Packet::fingerprint()
+}
+
 // TODO: Make this an enum of enums (for client-only, server-only, and common packets)?
 // Or maybe they should be entirely disjoint...
 pub mod packet {
     use crate::ecs::component::Component;
     use crate::ecs::Entity;
 
+    // Tags are pinned, so that clients and servers built from different revisions agree on what
+    // each packet is.  New packets should get the next unused tag, wherever they're declared.
+    //
+    // Strings are borrowed from the buffer the packet was received into, rather than copied out
+    // of it.
     #[derive(Debug, Serde)]
-    pub enum Packet {
+    pub enum Packet<'a> {
+        #[serde(tag = 0)]
         Hello {
-            name: String,
+            // Comes first, so it can still be read if the rest of the packet has changed.
+            protocol: u64,
+            name: &'a str,
         },
+        #[serde(tag = 1)]
         HelloAck,
+        #[serde(tag = 2)]
         CreateEntity {
             entity: Entity,
         },
+        #[serde(tag = 3)]
         SetComponent {
             entity: Entity,
             component: Component,
         },
+        /// Sent instead of `HelloAck` when the server won't let a client in.
+        #[serde(tag = 4)]
+        HelloReject {
+            reason: &'a str,
+        },
     }
 }
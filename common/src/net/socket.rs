@@ -1,7 +1,7 @@
 use std::io;
 use std::net::{SocketAddr, SocketAddrV4, UdpSocket};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, DeserializeError, Serialize, SliceWriter};
 
 use super::packet::*;
 
@@ -26,38 +26,40 @@ impl GameSocket {
         }
     }
 
-    /// Drains the queue of incoming packets on this socket and returns them in the order they were
-    /// received.
-    pub fn poll(&mut self) -> Vec<(Packet, SocketAddrV4)> {
-        let mut result: Vec<(Packet, SocketAddrV4)> = vec![];
-        loop {
-            let packet_info = match self.socket.recv_from(&mut self.packet_buf) {
-                Ok(p) => p,
-                // Once we've grabbed everything, break out of here.
-                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                Err(e) => panic!("encountered IO error: {}", e),
-            };
-            let (amt, src) = packet_info;
-            let src = match src {
-                SocketAddr::V4(s) => s,
-                SocketAddr::V6(_) => panic!("IPv6 currently unsupported"),
-            };
-            result.push((Packet::deserialize(&self.packet_buf[..amt]).1, src));
-        }
-        result
+    /// Returns the next packet waiting on this socket, if there is one.  The packet borrows from
+    /// this socket's buffer, so it has to be dropped before the socket is used again.
+    ///
+    /// A malformed packet is returned as an error (along with who sent it), so the caller can
+    /// skip it and carry on with the next one.
+    pub fn recv(&mut self) -> Option<(Result<Packet<'_>, DeserializeError>, SocketAddrV4)> {
+        let (amt, src) = match self.socket.recv_from(&mut self.packet_buf) {
+            Ok(p) => p,
+            // Once we've grabbed everything, there's nothing left to return.
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return None,
+            Err(e) => panic!("encountered IO error: {}", e),
+        };
+        let src = match src {
+            SocketAddr::V4(s) => s,
+            SocketAddr::V6(_) => panic!("IPv6 currently unsupported"),
+        };
+        let packet = Packet::deserialize(&self.packet_buf[..amt]).map(|(_, packet)| packet);
+        Some((packet, src))
     }
 
     pub fn send_to(&mut self, packet: Packet, dest: &SocketAddrV4) {
-        let bytes = packet.serialize();
-        if bytes.len() > PACKET_BUF_SIZE {
+        // Serialize straight into our buffer, rather than allocating a new one for every packet.
+        let mut writer = SliceWriter::new(&mut self.packet_buf);
+        packet.serialize_into(&mut writer);
+        if writer.overflowed() {
             panic!(
                 "serialized packet is too large ({} > {})",
-                bytes.len(),
+                writer.len(),
                 PACKET_BUF_SIZE
             );
         }
+        let len = writer.len();
         self.socket
-            .send_to(&bytes, dest)
+            .send_to(&self.packet_buf[..len], dest)
             .expect(&format!("failed to send {:?}", packet));
     }
 }
@@ -18,6 +18,7 @@ pub enum Intent {
     Right,
 }
 
+#[derive(Default)]
 pub struct ControlScheme(pub HashMap<Intent, Key>);
 
 impl ControlScheme {
@@ -34,22 +35,26 @@ impl ControlScheme {
     }
 }
 
-// TODO: Don't want to serialize this component, and there're probably some components in
-// general that we don't want to ever serialize.  Gotta figure out how to structure things
-// around that.
+#[derive(Serde)]
 pub struct PlayerComponent {
+    // Key bindings are local to whoever's at the keyboard, so they never go over the wire.
+    #[serde(skip)]
     control_scheme: ControlScheme,
 }
 
 pub fn new<T>(_control_scheme: ControlScheme, level: &mut Ecs<T>) -> Entity {
     let result = level.create_entity();
-    let mut comp_map = level.entity_map.borrow_mut(&result).unwrap();
-    comp_map.set(PositionComponent { x: 0.0, y: 0.0 });
-    //comp_map.set(PlayerComponent { control_scheme });
-    comp_map.set(RenderComponent {
-        color: COLOR,
-        size: SIZE,
-    });
+    level
+        .entity_map
+        .set(&result, PositionComponent { x: 0.0, y: 0.0 });
+    //level.entity_map.set(&result, PlayerComponent { control_scheme });
+    level.entity_map.set(
+        &result,
+        RenderComponent {
+            color: COLOR,
+            size: SIZE,
+        },
+    );
     result
 }
 
@@ -24,17 +24,21 @@ pub struct RandomMobComponent {
 
 pub fn new<T>(level: &mut Ecs<T>) -> Entity {
     let result = level.create_entity();
-    let mut comp_map = level.entity_map.borrow_mut(&result).unwrap();
-    comp_map.set(PositionComponent { x: 0.0, y: 0.0 });
+    level
+        .entity_map
+        .set(&result, PositionComponent { x: 0.0, y: 0.0 });
     // TODO: Get enum serialization support.
-    //comp_map.set(RandomMobComponent {
+    //level.entity_map.set(&result, RandomMobComponent {
     //    change_cnt: 0,
     //    curr_dir: Dir::Up,
     //});
-    comp_map.set(RenderComponent {
-        color: COLOR,
-        size: SIZE,
-    });
+    level.entity_map.set(
+        &result,
+        RenderComponent {
+            color: COLOR,
+            size: SIZE,
+        },
+    );
     result
 }
 
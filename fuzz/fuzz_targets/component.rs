@@ -0,0 +1,27 @@
+//! Throws arbitrary bytes at `Component::deserialize`, which covers every component that can be
+//! sent in a `Packet::SetComponent`, plus their delta encodings.
+//!
+//! Run with `cargo +nightly fuzz run component` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use common::ecs::component::{Component, PositionComponent, RenderComponent};
+use serde::{Deserialize, Serialize, SerializeDelta};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((_, component)) = Component::deserialize(data) {
+        let bytes = component.serialize();
+        Component::deserialize(&bytes).expect("re-serialized component didn't deserialize");
+    }
+
+    // Deltas are applied on top of whatever the receiver already has.
+    let mut position = PositionComponent { x: 0.0, y: 0.0 };
+    let _ = position.apply_delta(data);
+    let mut render = RenderComponent {
+        color: [0.0; 4],
+        size: 0.0,
+    };
+    let _ = render.apply_delta(data);
+});
@@ -0,0 +1,19 @@
+//! Throws arbitrary bytes at `Packet::deserialize`, which is fed straight from the network, so it
+//! has to reject anything malformed with an error instead of panicking.
+//!
+//! Run with `cargo +nightly fuzz run packet` from the repository root.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use common::net::packet::Packet;
+use serde::{Deserialize, Serialize};
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((_, packet)) = Packet::deserialize(data) {
+        // Whatever was accepted has to survive being sent back out.
+        let bytes = packet.serialize();
+        Packet::deserialize(&bytes).expect("re-serialized packet didn't deserialize");
+    }
+});
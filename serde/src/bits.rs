@@ -0,0 +1,482 @@
+//! Bit-packed encoding, for state that gets streamed every tick.
+//!
+//! `BitWriter` and `BitReader` don't round anything up to a whole byte: a `bool` or an `Option`'s
+//! presence flag takes up a single bit, an enum's tag takes up just enough bits to hold its
+//! largest tag, and fields marked `#[serde(bits = N)]` or `#[serde(quantize(...))]` take up
+//! exactly as many bits as they ask for.  Everything else is written as usual, just without
+//! being aligned to a byte boundary.
+//!
+//! The other formats understand `Bits` and `Quantize` fields too, but round them up to a whole
+//! number of bytes (or, for text, write the plain value).
+
+use std::convert::TryFrom;
+
+use crate::{
+    Config, Deserialize, DeserializeError, Deserializer, Serialize, Serializer, DEFAULT_MAX_LEN,
+    LATEST_VERSION,
+};
+
+/// Returns a mask covering the low `bits` bits of a `u64`.
+pub fn mask(bits: u32) -> u64 {
+    if bits >= 64 {
+        u64::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
+/// Maps `val` onto one of the `2^bits` evenly spaced steps from `min` to `max`, clamping it into
+/// that range first.  NaN maps to `min`.
+///
+/// `min` has to be less than `max`.  The derive checks this when they're literals, but not when
+/// they're constants.
+pub fn quantize(val: f64, min: f64, max: f64, bits: u32) -> u64 {
+    debug_assert!(max > min, "quantize range {}..={} is empty", min, max);
+    let steps = mask(bits) as f64;
+    let t = ((val - min) / (max - min)).clamp(0.0, 1.0);
+    (t * steps).round() as u64
+}
+
+/// Maps a step written by `quantize` back to a value.
+pub fn dequantize(step: u64, min: f64, max: f64, bits: u32) -> f64 {
+    debug_assert!(max > min, "quantize range {}..={} is empty", min, max);
+    let steps = mask(bits) as f64;
+    min + (max - min) * (step as f64 / steps)
+}
+
+/// Types that can be written in a given number of bits, for use with `#[serde(bits = N)]`
+/// fields.
+///
+/// Serializing a value that doesn't fit in the given number of bits panics, rather than quietly
+/// sending the wrong value.  Human-readable formats get the type's usual `Serialize`/`Deserialize`
+/// representation instead.
+pub trait Bits: Sized {
+    /// Streams the low `bits` bits of the value into `out`.
+    fn serialize_bits<S: Serializer + ?Sized>(&self, bits: u32, out: &mut S);
+
+    /// Attempts to read a value written by `serialize_bits` from the front of `de`.
+    fn deserialize_bits<'de, D: Deserializer<'de> + ?Sized>(
+        bits: u32,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError>;
+}
+
+macro_rules! impl_bits_unsigned {
+    ( $ty: ty ) => {
+        impl Bits for $ty {
+            fn serialize_bits<S: Serializer + ?Sized>(&self, bits: u32, out: &mut S) {
+                if out.is_human_readable() {
+                    return self.serialize_into(out);
+                }
+                let val = *self as u64;
+                assert!(
+                    val & !mask(bits) == 0,
+                    "{} doesn't fit in {} bits",
+                    val,
+                    bits
+                );
+                out.write_bits(val, bits);
+            }
+
+            fn deserialize_bits<'de, D: Deserializer<'de> + ?Sized>(
+                bits: u32,
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                if de.is_human_readable() {
+                    return Self::deserialize_from(de);
+                }
+                let val = de.read_bits(bits)?;
+                <$ty>::try_from(val).map_err(|_| DeserializeError::BitsOverflow { val, bits })
+            }
+        }
+    };
+}
+
+impl_bits_unsigned!(u8);
+impl_bits_unsigned!(u16);
+impl_bits_unsigned!(u32);
+impl_bits_unsigned!(u64);
+impl_bits_unsigned!(usize);
+
+// Signed values are written in two's complement, so `bits = 4` covers -8 to 7.
+macro_rules! impl_bits_signed {
+    ( $ty: ty ) => {
+        impl Bits for $ty {
+            fn serialize_bits<S: Serializer + ?Sized>(&self, bits: u32, out: &mut S) {
+                if out.is_human_readable() {
+                    return self.serialize_into(out);
+                }
+                let val = i128::from(*self);
+                let half = 1i128 << (bits - 1);
+                assert!(
+                    -half <= val && val < half,
+                    "{} doesn't fit in {} bits",
+                    val,
+                    bits
+                );
+                out.write_bits(val as u64 & mask(bits), bits);
+            }
+
+            fn deserialize_bits<'de, D: Deserializer<'de> + ?Sized>(
+                bits: u32,
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                if de.is_human_readable() {
+                    return Self::deserialize_from(de);
+                }
+                let raw = de.read_bits(bits)?;
+                // Sign-extend from the top bit we were given.
+                let val = if (raw >> (bits - 1)) & 1 == 1 {
+                    i128::from(raw) - (1i128 << bits)
+                } else {
+                    i128::from(raw)
+                };
+                <$ty>::try_from(val).map_err(|_| DeserializeError::BitsOverflow { val: raw, bits })
+            }
+        }
+    };
+}
+
+impl_bits_signed!(i8);
+impl_bits_signed!(i16);
+impl_bits_signed!(i32);
+impl_bits_signed!(i64);
+
+impl Bits for bool {
+    fn serialize_bits<S: Serializer + ?Sized>(&self, bits: u32, out: &mut S) {
+        if out.is_human_readable() {
+            return self.serialize_into(out);
+        }
+        out.write_bits(*self as u64, bits);
+    }
+
+    fn deserialize_bits<'de, D: Deserializer<'de> + ?Sized>(
+        bits: u32,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.is_human_readable() {
+            return Self::deserialize_from(de);
+        }
+        match de.read_bits(bits)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            val => Err(DeserializeError::BitsOverflow { val, bits }),
+        }
+    }
+}
+
+/// Floats that can be written as fixed-point values, for use with
+/// `#[serde(quantize(min = ..., max = ..., bits = ...))]` fields.
+///
+/// Values are clamped to `min..=max` and rounded to the nearest of `2^bits` evenly spaced steps,
+/// so they come back out slightly different from how they went in.  Human-readable formats get
+/// the exact value instead.
+pub trait Quantize: Sized {
+    /// Streams the quantized value into `out`.
+    fn serialize_quantized<S: Serializer + ?Sized>(
+        &self,
+        min: f64,
+        max: f64,
+        bits: u32,
+        out: &mut S,
+    );
+
+    /// Attempts to read a value written by `serialize_quantized` from the front of `de`.
+    fn deserialize_quantized<'de, D: Deserializer<'de> + ?Sized>(
+        min: f64,
+        max: f64,
+        bits: u32,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError>;
+}
+
+macro_rules! impl_quantize {
+    ( $ty: ty ) => {
+        impl Quantize for $ty {
+            fn serialize_quantized<S: Serializer + ?Sized>(
+                &self,
+                min: f64,
+                max: f64,
+                bits: u32,
+                out: &mut S,
+            ) {
+                if out.is_human_readable() {
+                    return self.serialize_into(out);
+                }
+                out.write_bits(quantize(f64::from(*self), min, max, bits), bits);
+            }
+
+            fn deserialize_quantized<'de, D: Deserializer<'de> + ?Sized>(
+                min: f64,
+                max: f64,
+                bits: u32,
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                if de.is_human_readable() {
+                    return Self::deserialize_from(de);
+                }
+                Ok(dequantize(de.read_bits(bits)?, min, max, bits) as $ty)
+            }
+        }
+    };
+}
+
+impl_quantize!(f32);
+impl_quantize!(f64);
+
+/// A `Serializer` that packs values into as few bits as possible.
+///
+/// The last byte is padded out with zeroes.
+pub struct BitWriter {
+    out: Vec<u8>,
+    /// How many bits of `out` are in use.
+    len: usize,
+    version: u32,
+    config: Config,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self {
+            out: vec![],
+            len: 0,
+            version: LATEST_VERSION,
+            config: Config::default(),
+        }
+    }
+
+    /// Writes in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Writes whole-byte values with the given options, instead of the default network format.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Returns the number of bits that have been written.
+    pub fn bit_len(&self) -> usize {
+        self.len
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.out
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.out.push(0);
+        }
+        if bit {
+            *self.out.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+        }
+        self.len += 1;
+    }
+}
+
+impl Default for BitWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer for BitWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.len.is_multiple_of(8) {
+            self.out.extend_from_slice(bytes);
+            self.len += bytes.len() * 8;
+        } else {
+            for &byte in bytes {
+                self.write_bits(u64::from(byte), 8);
+            }
+        }
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn write_bits(&mut self, val: u64, bits: u32) {
+        for i in (0..bits).rev() {
+            self.push_bit((val >> i) & 1 == 1);
+        }
+    }
+
+    fn write_bool(&mut self, val: bool) {
+        self.push_bit(val);
+    }
+
+    fn write_variant_tag(
+        &mut self,
+        _enum_name: &'static str,
+        _variant_name: &'static str,
+        tag: u64,
+        _tag_len: usize,
+        tag_bits: u32,
+    ) {
+        self.write_bits(tag, tag_bits);
+    }
+
+    fn write_none(&mut self) {
+        self.push_bit(false);
+    }
+
+    fn begin_some(&mut self) {
+        self.push_bit(true);
+    }
+}
+
+/// A `Deserializer` for input written by `BitWriter`.
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    /// How many bits of `data` have been consumed.
+    pos: usize,
+    version: u32,
+    config: Config,
+    max_len: usize,
+    /// Holds the output of `read_bytes` when the input isn't byte-aligned.
+    scratch: Vec<u8>,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            version: LATEST_VERSION,
+            config: Config::default(),
+            max_len: DEFAULT_MAX_LEN,
+            scratch: vec![],
+        }
+    }
+
+    /// Reads input that was written in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Reads whole-byte values with the given options, instead of the default network format.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces `DEFAULT_MAX_LEN` as the limit on the length of sequences, maps and strings.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Returns the number of bits consumed so far.
+    pub fn bits_read(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the number of bits that haven't been consumed yet, including the last byte's
+    /// padding.
+    pub fn remaining_bits(&self) -> usize {
+        self.data.len() * 8 - self.pos
+    }
+
+    fn check_remaining(&self, bits: usize) -> Result<(), DeserializeError> {
+        if self.remaining_bits() < bits {
+            return Err(DeserializeError::UnexpectedEof {
+                needed: bits.div_ceil(8),
+                remaining: self.remaining_bits() / 8,
+            });
+        }
+        Ok(())
+    }
+
+    /// Like `check_remaining`, but for whole bytes, which might not fit in a bit count.
+    fn check_remaining_bytes(&self, len: usize) -> Result<(), DeserializeError> {
+        if self.remaining_bits() / 8 < len {
+            return Err(DeserializeError::UnexpectedEof {
+                needed: len,
+                remaining: self.remaining_bits() / 8,
+            });
+        }
+        Ok(())
+    }
+
+    fn pop_bit(&mut self) -> bool {
+        let bit = self.data[self.pos / 8] & (0x80 >> (self.pos % 8)) != 0;
+        self.pos += 1;
+        bit
+    }
+}
+
+impl<'a> Deserializer<'a> for BitReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], DeserializeError> {
+        if self.pos.is_multiple_of(8) {
+            return self.read_borrowed_bytes(len);
+        }
+        self.check_remaining_bytes(len)?;
+        self.scratch.clear();
+        for _ in 0..len {
+            let byte = self.read_bits(8)? as u8;
+            self.scratch.push(byte);
+        }
+        Ok(&self.scratch)
+    }
+
+    /// Only works when the input is byte-aligned, since the bytes would have to be shifted
+    /// otherwise.
+    fn read_borrowed_bytes(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        if !self.pos.is_multiple_of(8) {
+            return Err(DeserializeError::CannotBorrow);
+        }
+        self.check_remaining_bytes(len)?;
+        let start = self.pos / 8;
+        self.pos += len * 8;
+        Ok(&self.data[start..start + len])
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    fn read_bits(&mut self, bits: u32) -> Result<u64, DeserializeError> {
+        self.check_remaining(bits as usize)?;
+        let mut result = 0;
+        for _ in 0..bits {
+            result = (result << 1) | self.pop_bit() as u64;
+        }
+        Ok(result)
+    }
+
+    fn read_bool(&mut self) -> Result<bool, DeserializeError> {
+        self.check_remaining(1)?;
+        Ok(self.pop_bit())
+    }
+
+    fn read_variant_tag(
+        &mut self,
+        _enum_name: &'static str,
+        _variants: &[(&'static str, u64)],
+        _tag_len: usize,
+        tag_bits: u32,
+    ) -> Result<u64, DeserializeError> {
+        self.read_bits(tag_bits)
+    }
+
+    fn read_option(&mut self) -> Result<bool, DeserializeError> {
+        self.read_bool()
+    }
+}
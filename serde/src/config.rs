@@ -0,0 +1,73 @@
+//! Options for the binary wire format, so the same types can be written in more than one flavor of
+//! it (e.g., the big-endian network format and a little-endian save format).
+//!
+//! Serializers and deserializers hand out their `Config` through `config()`, and the default
+//! methods of `Serializer` and `Deserializer` write and read primitives accordingly.  Both sides
+//! have to agree on the config, since nothing about it is written to the output.  Text formats
+//! ignore it.
+
+use crate::{DeserializeError, Deserializer, Serializer};
+
+/// The order of the bytes in multi-byte integers and floats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endian {
+    Big,
+    Little,
+}
+
+/// How many bytes a `usize` (including the length prefix of every sequence, map, and string) is
+/// written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsizeWidth {
+    U32,
+    U64,
+}
+
+/// The options for a binary format.  The default is the network format: big-endian, 8-byte
+/// `usize`s, and varints.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub endian: Endian,
+    pub usize_width: UsizeWidth,
+    /// Whether fields marked `#[serde(varint)]` use the variable-length encoding.  If not,
+    /// they're written like any other field.
+    pub varint: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            endian: Endian::Big,
+            usize_width: UsizeWidth::U64,
+            varint: true,
+        }
+    }
+}
+
+/// Writes the low `len` bytes of `val`, in the byte order `out` is configured with.
+pub(crate) fn write_uint<S: Serializer + ?Sized>(out: &mut S, val: u64, len: usize) {
+    match out.config().endian {
+        Endian::Big => out.write_bytes(&val.to_be_bytes()[8 - len..]),
+        Endian::Little => out.write_bytes(&val.to_le_bytes()[..len]),
+    }
+}
+
+/// Reads a `len`-byte unsigned integer written by `write_uint`.
+pub(crate) fn read_uint<'de, D: Deserializer<'de> + ?Sized>(
+    de: &mut D,
+    len: usize,
+) -> Result<u64, DeserializeError> {
+    let endian = de.config().endian;
+    let bytes = de.read_bytes(len)?;
+    let mut buf = [0u8; 8];
+    match endian {
+        Endian::Big => {
+            buf[8 - len..].copy_from_slice(bytes);
+            Ok(u64::from_be_bytes(buf))
+        }
+        Endian::Little => {
+            buf[..len].copy_from_slice(bytes);
+            Ok(u64::from_le_bytes(buf))
+        }
+    }
+}
@@ -0,0 +1,359 @@
+use std::convert::TryFrom;
+
+use crate::config::read_uint;
+use crate::{Config, DeserializeError, StructKind, UsizeWidth, DEFAULT_MAX_LEN, LATEST_VERSION};
+
+/// A source that serialized values are streamed out of.  `'de` is the lifetime of the input, for
+/// sources that can lend parts of it out (see `read_borrowed_bytes`).
+///
+/// Like `Serializer`, only `read_bytes` has to be implemented, and the rest of the methods
+/// default to reading the binary wire format.
+pub trait Deserializer<'de> {
+    /// Consumes and returns the next `len` bytes of input, or an `UnexpectedEof` error if there
+    /// aren't that many left.
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], DeserializeError>;
+
+    /// Like `read_bytes`, but returns a slice of the input itself, which outlives the
+    /// deserializer.  Sources that don't hold their input in memory (or that can't hand out this
+    /// part of it as is) return a `CannotBorrow` error.
+    fn read_borrowed_bytes(&mut self, _len: usize) -> Result<&'de [u8], DeserializeError> {
+        Err(DeserializeError::CannotBorrow)
+    }
+
+    /// The protocol version the input was written with.  Fields marked `#[serde(since = N)]` are
+    /// filled in with their defaults, instead of being read, if this is older than `N`.
+    fn version(&self) -> u32 {
+        LATEST_VERSION
+    }
+
+    /// The options for the binary format being read (see `config::Config`).
+    fn config(&self) -> Config {
+        Config::default()
+    }
+
+    /// The most elements a sequence or map (or bytes a string) can have before reading it fails
+    /// with a `TooLong` error.  Fields marked `#[serde(max_len = N)]` use `N` instead.
+    fn max_len(&self) -> usize {
+        DEFAULT_MAX_LEN
+    }
+
+    /// Returns true if this is a text format, in which case compact encodings like `Varint` don't
+    /// apply.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    // Primitives
+
+    fn read_u8(&mut self) -> Result<u8, DeserializeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DeserializeError> {
+        Ok(read_uint(self, 2)? as u16)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DeserializeError> {
+        Ok(read_uint(self, 4)? as u32)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DeserializeError> {
+        read_uint(self, 8)
+    }
+
+    fn read_i8(&mut self) -> Result<i8, DeserializeError> {
+        Ok(self.read_u8()? as i8)
+    }
+
+    fn read_i16(&mut self) -> Result<i16, DeserializeError> {
+        Ok(self.read_u16()? as i16)
+    }
+
+    fn read_i32(&mut self) -> Result<i32, DeserializeError> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    fn read_i64(&mut self) -> Result<i64, DeserializeError> {
+        Ok(self.read_u64()? as i64)
+    }
+
+    fn read_f32(&mut self) -> Result<f32, DeserializeError> {
+        Ok(f32::from_bits(self.read_u32()?))
+    }
+
+    fn read_f64(&mut self) -> Result<f64, DeserializeError> {
+        Ok(f64::from_bits(self.read_u64()?))
+    }
+
+    fn read_bool(&mut self) -> Result<bool, DeserializeError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            byte => Err(DeserializeError::InvalidBool { byte }),
+        }
+    }
+
+    fn read_usize(&mut self) -> Result<usize, DeserializeError> {
+        let val = match self.config().usize_width {
+            UsizeWidth::U32 => u64::from(self.read_u32()?),
+            UsizeWidth::U64 => self.read_u64()?,
+        };
+        // We at least check that the value fits, in case we're on a 32-bit target.
+        usize::try_from(val).map_err(|_| DeserializeError::LengthOverflow { len: val })
+    }
+
+    /// Reads a string of at most `max_len` bytes.
+    fn read_string(&mut self, max_len: usize) -> Result<String, DeserializeError> {
+        let size = check_len(self.read_usize()?, max_len)?;
+        crate::read_string(self, size)
+    }
+
+    /// Like `read_string`, but borrows the string from the input instead of copying it.
+    fn read_borrowed_str(&mut self, max_len: usize) -> Result<&'de str, DeserializeError> {
+        let size = check_len(self.read_usize()?, max_len)?;
+        std::str::from_utf8(self.read_borrowed_bytes(size)?)
+            .map_err(|_| DeserializeError::InvalidUtf8)
+    }
+
+    /// Reads a value written by `Serializer::write_bits`, failing if it doesn't fit in `bits`
+    /// bits.
+    fn read_bits(&mut self, bits: u32) -> Result<u64, DeserializeError> {
+        let val = read_uint(self, bits.div_ceil(8) as usize)?;
+        if val & !crate::bits::mask(bits) != 0 {
+            return Err(DeserializeError::BitsOverflow { val, bits });
+        }
+        Ok(val)
+    }
+
+    // Structure
+
+    fn begin_struct(
+        &mut self,
+        _name: &'static str,
+        _kind: StructKind,
+    ) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    fn begin_field(&mut self, _name: &'static str) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    fn end_struct(&mut self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    /// Reads which variant of an enum follows, returning its tag.  `variants` holds the name and
+    /// tag of every variant, for formats that identify variants by name.
+    fn read_variant_tag(
+        &mut self,
+        _enum_name: &'static str,
+        _variants: &[(&'static str, u64)],
+        tag_len: usize,
+        _tag_bits: u32,
+    ) -> Result<u64, DeserializeError> {
+        read_uint(self, tag_len)
+    }
+
+    /// Returns true if an `Option` holds a value, in which case it's followed by a call to
+    /// `end_some`.
+    fn read_option(&mut self) -> Result<bool, DeserializeError> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(DeserializeError::InvalidEnumTag {
+                type_name: "Option",
+                tag: u64::from(tag),
+            }),
+        }
+    }
+
+    fn end_some(&mut self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    /// Starts a sequence, returning its length if the format knows it up front.  If it doesn't,
+    /// `next_elem` says whether there's another element.
+    fn begin_seq(&mut self) -> Result<Option<usize>, DeserializeError> {
+        Ok(Some(self.read_usize()?))
+    }
+
+    /// Returns true if another element (or map entry) follows, for sequences and maps of unknown
+    /// length.
+    fn next_elem(&mut self) -> Result<bool, DeserializeError> {
+        Ok(false)
+    }
+
+    fn end_seq(&mut self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    /// Starts a map, returning its length if the format knows it up front.
+    fn begin_map(&mut self) -> Result<Option<usize>, DeserializeError> {
+        Ok(Some(self.read_usize()?))
+    }
+
+    fn end_map(&mut self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    fn begin_tuple(&mut self, _len: usize) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+
+    fn end_tuple(&mut self) -> Result<(), DeserializeError> {
+        Ok(())
+    }
+}
+
+/// Returns `len`, or a `TooLong` error if it's over `max_len`.
+pub(crate) fn check_len(len: usize, max_len: usize) -> Result<usize, DeserializeError> {
+    if len > max_len {
+        return Err(DeserializeError::TooLong { len, max_len });
+    }
+    Ok(len)
+}
+
+/// Reads the elements of a sequence of at most `max_len` elements, calling `read_elem` for each
+/// one.
+///
+/// The length is checked before any elements are read, so a forged length prefix can't make us
+/// spin on elements that don't take up any input (like `()`).  Sequences of unknown length are
+/// checked as they go.
+pub(crate) fn read_seq<'de, D, F>(
+    de: &mut D,
+    max_len: usize,
+    read_elem: F,
+) -> Result<(), DeserializeError>
+where
+    D: Deserializer<'de> + ?Sized,
+    F: FnMut(&mut D) -> Result<(), DeserializeError>,
+{
+    let len = de.begin_seq()?;
+    read_elems(de, len, max_len, read_elem)?;
+    de.end_seq()
+}
+
+/// Like `read_seq`, but for the entries of a map.
+pub(crate) fn read_map<'de, D, F>(
+    de: &mut D,
+    max_len: usize,
+    read_entry: F,
+) -> Result<(), DeserializeError>
+where
+    D: Deserializer<'de> + ?Sized,
+    F: FnMut(&mut D) -> Result<(), DeserializeError>,
+{
+    let len = de.begin_map()?;
+    read_elems(de, len, max_len, read_entry)?;
+    de.end_map()
+}
+
+/// Calls `read_elem` for each element of a sequence or map, which is `len` long if the format
+/// knows its length up front.
+fn read_elems<'de, D, F>(
+    de: &mut D,
+    len: Option<usize>,
+    max_len: usize,
+    mut read_elem: F,
+) -> Result<(), DeserializeError>
+where
+    D: Deserializer<'de> + ?Sized,
+    F: FnMut(&mut D) -> Result<(), DeserializeError>,
+{
+    match len {
+        Some(len) => {
+            for _ in 0..check_len(len, max_len)? {
+                read_elem(de)?;
+            }
+        }
+        None => {
+            let mut count = 0;
+            while de.next_elem()? {
+                count = check_len(count + 1, max_len)?;
+                read_elem(de)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A cursor over a byte slice.
+pub struct SliceReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    version: u32,
+    config: Config,
+    max_len: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            version: LATEST_VERSION,
+            config: Config::default(),
+            max_len: DEFAULT_MAX_LEN,
+        }
+    }
+
+    /// Reads input that was written in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Reads input that was written with the given options, instead of the default network
+    /// format.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Replaces `DEFAULT_MAX_LEN` as the limit on the length of sequences, maps and strings.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Returns the number of bytes consumed so far.
+    pub fn bytes_read(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns the number of bytes that haven't been consumed yet.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+impl<'a> Deserializer<'a> for SliceReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], DeserializeError> {
+        self.read_borrowed_bytes(len)
+    }
+
+    fn read_borrowed_bytes(&mut self, len: usize) -> Result<&'a [u8], DeserializeError> {
+        if self.remaining() < len {
+            return Err(DeserializeError::UnexpectedEof {
+                needed: len,
+                remaining: self.remaining(),
+            });
+        }
+        let result = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(result)
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn config(&self) -> Config {
+        self.config
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
@@ -0,0 +1,37 @@
+//! Delta encoding, for resending a value that's mostly the same as one the other side already
+//! has.
+
+use crate::{DeserializeError, DeserializeOwned, Deserializer, Serialize, Serializer, SliceReader};
+
+/// Types that can be written as the difference from an earlier value, generated by
+/// `#[derive(Serde)]` for types marked `#[serde(delta)]`.
+///
+/// Structs write a bitmask of which fields changed, followed by just those fields.  Enums write a
+/// single bit, followed by the whole value if it changed.  The mask goes through `write_bits`, so
+/// `bits::BitWriter` packs it into exactly one bit per field.
+pub trait SerializeDelta: Serialize + DeserializeOwned {
+    /// Streams the parts of `self` that differ from `base` into `out`.
+    fn serialize_delta_into<S: Serializer + ?Sized>(&self, base: &Self, out: &mut S);
+
+    /// Reads a delta written by `serialize_delta_into` from the front of `de`, and applies it to
+    /// `self`, which should be the same as the `base` it was written against.  If this fails,
+    /// some of the fields may have already been updated.
+    fn apply_delta_from<'de, D: Deserializer<'de> + ?Sized>(
+        &mut self,
+        de: &mut D,
+    ) -> Result<(), DeserializeError>;
+
+    /// Serializes the difference from `base` into a vector of bytes.
+    fn delta(&self, base: &Self) -> Vec<u8> {
+        let mut result = Vec::new();
+        self.serialize_delta_into(base, &mut result);
+        result
+    }
+
+    /// Applies a delta from a vector of bytes, returning the number of bytes read.
+    fn apply_delta(&mut self, data: &[u8]) -> Result<usize, DeserializeError> {
+        let mut reader = SliceReader::new(data);
+        self.apply_delta_from(&mut reader)?;
+        Ok(reader.bytes_read())
+    }
+}
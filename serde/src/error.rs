@@ -0,0 +1,71 @@
+use std::error::Error;
+use std::fmt;
+
+/// Everything that can go wrong when deserializing untrusted bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeserializeError {
+    /// The input ended before the value was fully read.
+    UnexpectedEof { needed: usize, remaining: usize },
+    /// A `String` contained bytes that aren't valid UTF-8.
+    InvalidUtf8,
+    /// A `bool` was something other than 0 or 1.
+    InvalidBool { byte: u8 },
+    /// An enum tag didn't correspond to any variant of the enum being read.
+    InvalidEnumTag { type_name: &'static str, tag: u64 },
+    /// A length or size was too large to be represented on this platform.
+    LengthOverflow { len: u64 },
+    /// A sequence, map or string was longer than the deserializer (or the field being read)
+    /// allows.
+    TooLong { len: usize, max_len: usize },
+    /// A variable-length integer was overlong or out of range for the type being read.
+    InvalidVarint,
+    /// A bit-packed value (see `bits::Bits`) was too large for the number of bits it was
+    /// supposed to take up, or for the type being read.
+    BitsOverflow { val: u64, bits: u32 },
+    /// A `&str` or `&[u8]` couldn't be borrowed from the input, because the input isn't in memory
+    /// or the value isn't stored there as is (e.g., it's an escaped string).
+    CannotBorrow,
+    /// Text input (see `text::TextReader`) didn't match what was expected.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DeserializeError::UnexpectedEof { needed, remaining } => write!(
+                f,
+                "unexpected end of input (needed {} bytes, {} remaining)",
+                needed, remaining
+            ),
+            DeserializeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DeserializeError::InvalidBool { byte } => {
+                write!(f, "invalid bool (byte was {})", byte)
+            }
+            DeserializeError::InvalidEnumTag { type_name, tag } => {
+                write!(f, "invalid tag for enum `{}` (tag was {})", type_name, tag)
+            }
+            DeserializeError::LengthOverflow { len } => {
+                write!(f, "length {} doesn't fit in a `usize`", len)
+            }
+            DeserializeError::TooLong { len, max_len } => {
+                write!(f, "length {} is over the limit of {}", len, max_len)
+            }
+            DeserializeError::InvalidVarint => write!(f, "invalid variable-length integer"),
+            DeserializeError::BitsOverflow { val, bits } => {
+                write!(f, "value {} doesn't fit in {} bits", val, bits)
+            }
+            DeserializeError::CannotBorrow => write!(f, "value can't be borrowed from the input"),
+            DeserializeError::Syntax {
+                line,
+                column,
+                ref message,
+            } => write!(f, "{} at line {}, column {}", message, line, column),
+        }
+    }
+}
+
+impl Error for DeserializeError {}
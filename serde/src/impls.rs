@@ -0,0 +1,212 @@
+//! Implementations for standard library types beyond the primitives in the crate root.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+use std::marker::PhantomData;
+
+use crate::{Deserialize, DeserializeError, Deserializer, MaxLen, Serialize, Serializer};
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        match *self {
+            None => out.write_none(),
+            Some(ref val) => {
+                out.begin_some();
+                val.serialize_into(out);
+                out.end_some();
+            }
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Option<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.read_option()? {
+            let val = T::deserialize_from(de)?;
+            de.end_some()?;
+            Ok(Some(val))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for Box<T> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        (**self).serialize_into(out);
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Box<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Ok(Box::new(T::deserialize_from(de)?))
+    }
+}
+
+// `PhantomData` doesn't hold anything, so it's written like `()`.
+impl<T: ?Sized> Serialize for PhantomData<T> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_tuple(0);
+        out.end_tuple();
+    }
+}
+
+impl<'de, T: ?Sized> Deserialize<'de> for PhantomData<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.begin_tuple(0)?;
+        de.end_tuple()?;
+        Ok(PhantomData)
+    }
+}
+
+// Tuples are written as their elements, one after another, just like a tuple struct.
+macro_rules! impl_tuple {
+    ( $len: expr $(, $name: ident )* ) => {
+        impl<$( $name: Serialize ),*> Serialize for ( $( $name, )* ) {
+            #[allow(non_snake_case)]
+            fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+                let ( $( ref $name, )* ) = *self;
+                out.begin_tuple($len);
+                $( $name.serialize_into(out); )*
+                out.end_tuple();
+            }
+        }
+
+        impl<'de, $( $name: Deserialize<'de> ),*> Deserialize<'de> for ( $( $name, )* ) {
+            #[allow(non_snake_case)]
+            fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                de.begin_tuple($len)?;
+                $( let $name = $name::deserialize_from(de)?; )*
+                de.end_tuple()?;
+                Ok(( $( $name, )* ))
+            }
+        }
+    };
+}
+
+impl_tuple!(0);
+impl_tuple!(1, T0);
+impl_tuple!(2, T0, T1);
+impl_tuple!(3, T0, T1, T2);
+impl_tuple!(4, T0, T1, T2, T3);
+impl_tuple!(5, T0, T1, T2, T3, T4);
+impl_tuple!(6, T0, T1, T2, T3, T4, T5);
+impl_tuple!(7, T0, T1, T2, T3, T4, T5, T6);
+impl_tuple!(8, T0, T1, T2, T3, T4, T5, T6, T7);
+impl_tuple!(9, T0, T1, T2, T3, T4, T5, T6, T7, T8);
+impl_tuple!(10, T0, T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_tuple!(11, T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_tuple!(12, T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+
+// Sequences and maps are written like `Vec<T>`: a length prefix, followed by each element (or
+// key-value pair) in iteration order.  They're read by their `MaxLen` impls, with the
+// deserializer's limit.
+
+impl<T: Serialize> Serialize for VecDeque<T> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_seq(self.len());
+        for val in self.iter() {
+            val.serialize_into(out);
+        }
+        out.end_seq();
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for VecDeque<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+impl<T: Serialize, H> Serialize for HashSet<T, H> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_seq(self.len());
+        for val in self.iter() {
+            val.serialize_into(out);
+        }
+        out.end_seq();
+    }
+}
+
+impl<'de, T, H> Deserialize<'de> for HashSet<T, H>
+where
+    T: Deserialize<'de> + Eq + Hash,
+    H: BuildHasher + Default,
+{
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+impl<T: Serialize> Serialize for BTreeSet<T> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_seq(self.len());
+        for val in self.iter() {
+            val.serialize_into(out);
+        }
+        out.end_seq();
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Ord> Deserialize<'de> for BTreeSet<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+impl<K: Serialize, V: Serialize, H> Serialize for HashMap<K, V, H> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_map(self.len());
+        for (key, val) in self.iter() {
+            key.serialize_into(out);
+            val.serialize_into(out);
+        }
+        out.end_map();
+    }
+}
+
+impl<'de, K, V, H> Deserialize<'de> for HashMap<K, V, H>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    H: BuildHasher + Default,
+{
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_map(self.len());
+        for (key, val) in self.iter() {
+            key.serialize_into(out);
+            val.serialize_into(out);
+        }
+        out.end_map();
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> Deserialize<'de> for BTreeMap<K, V> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
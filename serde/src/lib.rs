@@ -1,199 +1,296 @@
-use std::mem;
+pub mod bits;
+pub mod config;
+mod de;
+mod delta;
+mod error;
+mod impls;
+mod max_len;
+pub mod schema;
+mod ser;
+pub mod text;
+pub mod varint;
+
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::ptr;
+
+pub use self::bits::{BitReader, BitWriter, Bits, Quantize};
+pub use self::config::{Config, Endian, UsizeWidth};
+pub use self::de::{Deserializer, SliceReader};
+pub use self::delta::SerializeDelta;
+pub use self::error::DeserializeError;
+pub use self::max_len::MaxLen;
+pub use self::schema::Schema;
+pub use self::ser::{Serializer, SliceWriter, VecWriter};
+pub use self::varint::Varint;
+
+/// The version that serializers and deserializers use unless they're told otherwise.  Every field
+/// is present in this version, regardless of its `#[serde(since = N)]` attribute.
+pub const LATEST_VERSION: u32 = u32::MAX;
+
+/// The most elements a sequence or map (or bytes a string) can have, unless the deserializer is
+/// told otherwise.  This keeps a forged length prefix from costing more work than a datagram's
+/// worth of elements, and is well over the length of anything that fits in one.
+pub const DEFAULT_MAX_LEN: usize = 1 << 16;
 
 pub trait Serialize {
+    /// Streams the serialized form of the type into `out`.
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S);
+
     /// Serializes the type into a vector of bytes.
-    fn serialize(&self) -> Vec<u8>;
+    fn serialize(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        self.serialize_into(&mut result);
+        result
+    }
+
+    /// Like `serialize`, but with the given options instead of the default network format.
+    fn serialize_with(&self, config: Config) -> Vec<u8> {
+        let mut writer = VecWriter::new().with_config(config);
+        self.serialize_into(&mut writer);
+        writer.into_bytes()
+    }
+}
+
+pub trait Deserialize<'de>: Sized {
+    /// Attempts to deserialize an instance of `Self` from the front of `de`, leaving it positioned
+    /// just past the bytes that were consumed.
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError>;
+
+    /// Attempts to deserialize an instance of `Self` from a slice of bytes, which `Self` may
+    /// borrow from.  Returns the number of bytes read, along with the constructed `Self` instance,
+    /// or an error if `data` doesn't hold a valid `Self`.
+    fn deserialize(data: &'de [u8]) -> Result<(usize, Self), DeserializeError> {
+        let mut reader = SliceReader::new(data);
+        let result = Self::deserialize_from(&mut reader)?;
+        Ok((reader.bytes_read(), result))
+    }
+
+    /// Like `deserialize`, but for bytes written with the given options instead of the default
+    /// network format.
+    fn deserialize_with(
+        data: &'de [u8],
+        config: Config,
+    ) -> Result<(usize, Self), DeserializeError> {
+        let mut reader = SliceReader::new(data).with_config(config);
+        let result = Self::deserialize_from(&mut reader)?;
+        Ok((reader.bytes_read(), result))
+    }
 }
 
-pub trait Deserialize {
-    // TODO: Make return type `io::Result`.
-    /// Attempts to deserialize an instance of `Self` from a vector of bytes.  Returns the number of
-    /// bytes read, along with the constructed `Self` instance.
-    fn deserialize(data: &[u8]) -> (usize, Self);
+/// Types that don't borrow anything from the input they're deserialized from, so they can be read
+/// from a buffer that's about to be reused.
+pub trait DeserializeOwned: for<'de> Deserialize<'de> {}
+
+impl<T: for<'de> Deserialize<'de>> DeserializeOwned for T {}
+
+/// The layout of a struct or enum variant's fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StructKind {
+    /// `struct Foo { x: u32 }`
+    Named,
+    /// `struct Foo(u32)`
+    Tuple,
+    /// `struct Foo`
+    Unit,
 }
 
 // Primitive/Useful Type Trait Implementations
 
-macro_rules! impl_integral {
-    ( $ty: ty, $num_bytes: expr ) => {
+macro_rules! impl_primitive {
+    ( $ty: ty, $write_fn: ident, $read_fn: ident ) => {
         impl Serialize for $ty {
-            fn serialize(&self) -> Vec<u8> {
-                let mut result = Vec::with_capacity($num_bytes);
-                for shift in (0..$num_bytes).map(|v| v * 8).rev() {
-                    result.push(((*self >> shift) & 0xff) as u8);
-                }
-                result
+            fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+                out.$write_fn(*self);
             }
         }
 
-        impl Deserialize for $ty {
-            fn deserialize(data: &[u8]) -> (usize, Self) {
-                let mut result = 0;
-                let shift_iter = (0..$num_bytes).map(|v| v * 8).rev();
-                for (byte, shift) in data.iter().zip(shift_iter) {
-                    result += (*byte as $ty) << shift;
-                }
-                ($num_bytes, result)
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                de.$read_fn()
             }
         }
     };
 }
 
-impl_integral!(u8, 1);
-impl_integral!(u16, 2);
-impl_integral!(u32, 4);
-impl_integral!(u64, 8);
+impl_primitive!(u8, write_u8, read_u8);
+impl_primitive!(u16, write_u16, read_u16);
+impl_primitive!(u32, write_u32, read_u32);
+impl_primitive!(u64, write_u64, read_u64);
+impl_primitive!(i8, write_i8, read_i8);
+impl_primitive!(i16, write_i16, read_i16);
+impl_primitive!(i32, write_i32, read_i32);
+impl_primitive!(i64, write_i64, read_i64);
+impl_primitive!(f32, write_f32, read_f32);
+impl_primitive!(f64, write_f64, read_f64);
+impl_primitive!(bool, write_bool, read_bool);
+impl_primitive!(usize, write_usize, read_usize);
 
-// We leech off of the unsigned integral implementations above to work with signed integer and float
-// types.
-//
-// Unfortunately, we need two separate arguments for specifying which integral type's implementation
-// to use, because in the macro, one of them needs to be a type, and one of them needs to be an
-// identifier.
-macro_rules! impl_leech {
-    ( $ty: ty, $uint_ty: ty, $uint_ident: ident ) => {
-        impl Serialize for $ty {
-            fn serialize(&self) -> Vec<u8> {
-                let as_uint: $uint_ty = unsafe { ::std::mem::transmute(*self) };
-                as_uint.serialize()
-            }
+// Arrays are written like tuples, since their length is part of their type.
+impl<T: Serialize, const N: usize> Serialize for [T; N] {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_tuple(N);
+        for val in self.iter() {
+            val.serialize_into(out);
         }
+        out.end_tuple();
+    }
+}
 
-        impl Deserialize for $ty {
-            fn deserialize(data: &[u8]) -> (usize, Self) {
-                let (bytes_read, as_uint) = $uint_ident::deserialize(data);
-                let as_ty = unsafe { ::std::mem::transmute(as_uint) };
-                (bytes_read, as_ty)
-            }
+impl<'de, T: Deserialize<'de>, const N: usize> Deserialize<'de> for [T; N] {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.begin_tuple(N)?;
+        let mut builder = ArrayBuilder::new();
+        for _ in 0..N {
+            // If this fails, `builder` takes care of dropping the elements we've already read.
+            builder.push(T::deserialize_from(de)?);
         }
-    };
+        de.end_tuple()?;
+        Ok(builder.finish())
+    }
 }
 
-impl_leech!(f32, u32, u32);
-impl_leech!(f64, u64, u64);
+/// Builds up a `[T; N]` one element at a time, without requiring `T: Default` or `T: Copy`.
+///
+/// If the builder is dropped before it's full, the elements pushed so far are dropped with it.
+struct ArrayBuilder<T, const N: usize> {
+    data: [MaybeUninit<T>; N],
+    len: usize,
+}
 
-impl_leech!(i8, u8, u8);
-impl_leech!(i16, u16, u16);
-impl_leech!(i32, u32, u32);
-impl_leech!(i64, u64, u64);
+impl<T, const N: usize> ArrayBuilder<T, N> {
+    fn new() -> Self {
+        Self {
+            data: [(); N].map(|_| MaybeUninit::uninit()),
+            len: 0,
+        }
+    }
 
-impl Serialize for usize {
-    fn serialize(&self) -> Vec<u8> {
-        // Hecking WHAT?!  Did you just assume my computer's word size?
-        (*self as u64).serialize()
+    fn push(&mut self, val: T) {
+        self.data[self.len] = MaybeUninit::new(val);
+        self.len += 1;
     }
-}
 
-impl Deserialize for usize {
-    fn deserialize(data: &[u8]) -> (usize, Self) {
-        // Yes I did.
-        let (bytes_read, int_result) = <u64>::deserialize(data);
-        (bytes_read, int_result as usize)
+    fn finish(self) -> [T; N] {
+        assert_eq!(self.len, N, "array isn't full yet");
+        // We're handing ownership of the elements to the caller, so our `Drop` impl can't run.
+        let this = ManuallyDrop::new(self);
+        // Safe, because every element has been initialized, and `MaybeUninit<T>` has the same
+        // layout as `T`.
+        unsafe { ptr::read(&this.data as *const [MaybeUninit<T>; N] as *const [T; N]) }
     }
 }
 
-macro_rules! impl_array {
-    ( $len: expr ) => {
-        impl<T: Serialize> Serialize for [T; $len] {
-            fn serialize(&self) -> Vec<u8> {
-                let mut result = vec![];
-                for val in self.iter() {
-                    result.append(&mut val.serialize());
-                }
-                result
-            }
+impl<T, const N: usize> Drop for ArrayBuilder<T, N> {
+    fn drop(&mut self) {
+        for elem in self.data[..self.len].iter_mut() {
+            // Safe, because the first `len` elements have been initialized.
+            unsafe { ptr::drop_in_place(elem.as_mut_ptr()) };
         }
-
-        impl<T: Deserialize + Default> Deserialize for [T; $len] {
-            fn deserialize(data: &[u8]) -> (usize, Self) {
-                let mut result: [T; $len] = unsafe { mem::uninitialized() };
-                let mut bytes_read = 0;
-                for i in 0..$len {
-                    let deser_data = T::deserialize(&data[bytes_read..]);
-                    bytes_read += deser_data.0;
-                    result[i] = deser_data.1;
-                }
-                (bytes_read, result)
-            }
-        }
-    };
+    }
 }
 
-// No type-level integers, so we have to choose a set of array lengths that we might want to use
-// and implement the traits for all of those lengths.
-impl_array!(0);
-impl_array!(1);
-impl_array!(2);
-impl_array!(3);
-impl_array!(4);
-impl_array!(5);
-impl_array!(6);
-impl_array!(7);
-impl_array!(8);
-impl_array!(9);
-impl_array!(10);
-impl_array!(11);
-impl_array!(12);
-impl_array!(13);
-impl_array!(14);
-impl_array!(15);
-impl_array!(16);
-impl_array!(17);
-impl_array!(18);
-impl_array!(19);
-impl_array!(20);
-impl_array!(21);
-impl_array!(22);
-impl_array!(23);
-impl_array!(24);
-impl_array!(25);
-impl_array!(26);
-impl_array!(27);
-impl_array!(28);
-impl_array!(29);
-impl_array!(30);
-impl_array!(31);
-impl_array!(32);
-
 impl Serialize for String {
-    fn serialize(&self) -> Vec<u8> {
-        let mut result = self.len().serialize();
-        result.append(&mut self.bytes().collect());
-        result
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.write_str(self);
     }
 }
 
-impl Deserialize for String {
-    fn deserialize(data: &[u8]) -> (usize, Self) {
-        let (bytes_read, size) = usize::deserialize(data);
-        let result = std::str::from_utf8(&data[bytes_read..])
-            .expect("failed to deserialize string")
-            .to_string();
-        assert_eq!(result.len(), size);
-        (bytes_read + size, result)
+impl<'de> Deserialize<'de> for String {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.read_string(de.max_len())
     }
 }
 
 impl<T: Serialize> Serialize for Vec<T> {
-    fn serialize(&self) -> Vec<u8> {
-        let mut result = self.len().serialize();
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_seq(self.len());
         for val in self.iter() {
-            result.append(&mut val.serialize());
+            val.serialize_into(out);
         }
-        result
+        out.end_seq();
     }
 }
 
-impl<T: Deserialize> Deserialize for Vec<T> {
-    fn deserialize(data: &[u8]) -> (usize, Self) {
-        let (mut bytes_read, size) = usize::deserialize(data);
-        let mut result = Vec::new();
-        for _ in 0..size {
-            let deser_data = T::deserialize(&data[bytes_read..]);
-            bytes_read += deser_data.0;
-            result.push(deser_data.1);
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Vec<T> {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+impl Serialize for str {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.write_str(self);
+    }
+}
+
+impl<T: Serialize> Serialize for [T] {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        out.begin_seq(self.len());
+        for val in self.iter() {
+            val.serialize_into(out);
         }
-        assert_eq!(result.len(), size);
-        (bytes_read, result)
+        out.end_seq();
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for &T {
+    fn serialize_into<S: Serializer + ?Sized>(&self, out: &mut S) {
+        (**self).serialize_into(out);
+    }
+}
+
+// `&str` and `&[u8]` point straight into the input instead of copying out of it, so they can only
+// be read from formats that keep it in memory, and only if they're stored there as is.  Otherwise,
+// reading them fails with `DeserializeError::CannotBorrow`.
+
+impl<'de: 'a, 'a> Deserialize<'de> for &'a str {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.read_borrowed_str(de.max_len())
+    }
+}
+
+// Written like any other sequence of `u8`s.
+impl<'de: 'a, 'a> Deserialize<'de> for &'a [u8] {
+    fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        Self::deserialize_max_len(de.max_len(), de)
+    }
+}
+
+// Helpers for reading the contents of a container, once its length prefix has been read (and
+// checked against the limit).
+
+pub(crate) fn read_string<'de, D: Deserializer<'de> + ?Sized>(
+    de: &mut D,
+    size: usize,
+) -> Result<String, DeserializeError> {
+    let str_bytes = de.read_bytes(size)?;
+    let result = std::str::from_utf8(str_bytes)
+        .map_err(|_| DeserializeError::InvalidUtf8)?
+        .to_string();
+    Ok(result)
+}
+
+pub(crate) fn read_vec<'de, T: Deserialize<'de>, D: Deserializer<'de> + ?Sized>(
+    de: &mut D,
+    size: usize,
+) -> Result<Vec<T>, DeserializeError> {
+    let mut result = Vec::new();
+    for _ in 0..size {
+        result.push(T::deserialize_from(de)?);
     }
+    Ok(result)
 }
@@ -0,0 +1,165 @@
+//! Limits on how long sequences, maps and strings can be, for use with `#[serde(max_len = N)]`
+//! fields.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hash};
+
+use crate::de::{check_len, read_map, read_seq};
+use crate::{Deserialize, DeserializeError, Deserializer};
+
+/// Types with a length that's limited while they're being read.
+///
+/// Their `Deserialize` impls use the deserializer's `max_len`, but this lets a field pick its own
+/// limit, which can be more or less than that.  It only applies to the outermost container, so
+/// the elements of a `Vec<String>` still use the deserializer's limit.  The limit isn't checked
+/// when writing.
+pub trait MaxLen<'de>: Sized {
+    /// Attempts to read a value from the front of `de`, failing with a `TooLong` error if it has
+    /// more than `max_len` elements (or bytes, for strings).
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError>;
+}
+
+impl<'de, T: MaxLen<'de>> MaxLen<'de> for Option<T> {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.read_option()? {
+            let val = T::deserialize_max_len(max_len, de)?;
+            de.end_some()?;
+            Ok(Some(val))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl<'de> MaxLen<'de> for String {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.read_string(max_len)
+    }
+}
+
+impl<'de: 'a, 'a> MaxLen<'de> for &'a str {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        de.read_borrowed_str(max_len)
+    }
+}
+
+impl<'de: 'a, 'a> MaxLen<'de> for &'a [u8] {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let len = de.begin_seq()?.ok_or(DeserializeError::CannotBorrow)?;
+        let result = de.read_borrowed_bytes(check_len(len, max_len)?)?;
+        de.end_seq()?;
+        Ok(result)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> MaxLen<'de> for Vec<T> {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = Vec::new();
+        read_seq(de, max_len, |de| {
+            result.push(T::deserialize_from(de)?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> MaxLen<'de> for VecDeque<T> {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = VecDeque::new();
+        read_seq(de, max_len, |de| {
+            result.push_back(T::deserialize_from(de)?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+impl<'de, T, H> MaxLen<'de> for HashSet<T, H>
+where
+    T: Deserialize<'de> + Eq + Hash,
+    H: BuildHasher + Default,
+{
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = HashSet::default();
+        read_seq(de, max_len, |de| {
+            result.insert(T::deserialize_from(de)?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Ord> MaxLen<'de> for BTreeSet<T> {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = BTreeSet::new();
+        read_seq(de, max_len, |de| {
+            result.insert(T::deserialize_from(de)?);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+impl<'de, K, V, H> MaxLen<'de> for HashMap<K, V, H>
+where
+    K: Deserialize<'de> + Eq + Hash,
+    V: Deserialize<'de>,
+    H: BuildHasher + Default,
+{
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = HashMap::default();
+        read_map(de, max_len, |de| {
+            let key = K::deserialize_from(de)?;
+            let val = V::deserialize_from(de)?;
+            result.insert(key, val);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+impl<'de, K: Deserialize<'de> + Ord, V: Deserialize<'de>> MaxLen<'de> for BTreeMap<K, V> {
+    fn deserialize_max_len<D: Deserializer<'de> + ?Sized>(
+        max_len: usize,
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        let mut result = BTreeMap::new();
+        read_map(de, max_len, |de| {
+            let key = K::deserialize_from(de)?;
+            let val = V::deserialize_from(de)?;
+            result.insert(key, val);
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,367 @@
+//! Descriptions of what types look like on the wire.
+//!
+//! `#[derive(Serde)]` implements `Schema` alongside `Serialize` and `Deserialize`, so two builds
+//! can compare fingerprints to find out whether they'd actually understand each other, instead of
+//! finding out from garbled packets.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::marker::PhantomData;
+
+/// Types that can describe their own wire format.
+pub trait Schema {
+    /// Returns a description of how `Self` is written.
+    fn schema() -> Shape;
+
+    /// Returns a stable hash of `schema`, including the schemas of every type it contains.
+    fn fingerprint() -> u64 {
+        fingerprint(&Self::schema())
+    }
+}
+
+/// A lazily evaluated schema for a nested type.  Nested schemas are only built when they're
+/// walked, so self-referential types (e.g., through a `Box`) don't recurse forever.
+pub type SchemaFn = fn() -> Shape;
+
+/// The shape of a type on the wire.
+#[derive(Clone)]
+pub enum Shape {
+    /// A type with a fixed encoding, named by its Rust type (e.g., `"u32"`, `"String"`).
+    Primitive(&'static str),
+    /// A one-byte presence flag, followed by the value if it's present.
+    Option(SchemaFn),
+    /// A length prefix, followed by that many elements.
+    Seq(SchemaFn),
+    /// A length prefix, followed by that many key-value pairs.
+    Map(SchemaFn, SchemaFn),
+    /// A fixed number of elements, with no length prefix.
+    Array(SchemaFn, usize),
+    /// Each element, one after another.
+    Tuple(Vec<SchemaFn>),
+    /// Each field, one after another.
+    Struct {
+        name: &'static str,
+        fields: Vec<Field>,
+    },
+    /// A variant tag, followed by that variant's fields.
+    Enum {
+        name: &'static str,
+        variants: Vec<Variant>,
+    },
+}
+
+/// A single (non-skipped) field of a struct or enum variant.
+#[derive(Clone)]
+pub struct Field {
+    /// The field's name, or its index for tuple structs and variants.
+    pub name: &'static str,
+    /// The field's type, as written in the type definition.
+    pub ty: &'static str,
+    pub encoding: Encoding,
+    /// The protocol version the field was added in, if it's marked `#[serde(since = N)]`.
+    pub since: Option<u32>,
+}
+
+/// How a field is written.
+#[derive(Clone)]
+pub enum Encoding {
+    /// The field type's own `Serialize` impl, described by the given schema.
+    Default(SchemaFn),
+    /// The field type's `Varint` impl.  The schema is still the field type's, since that's what
+    /// tells a `u32` and a `u64` apart.
+    Varint(SchemaFn),
+    /// The field type's `Bits` impl, in the given number of bits.
+    Bits(SchemaFn, u32),
+    /// The field type's `Quantize` impl.
+    Quantized {
+        schema: SchemaFn,
+        min: f64,
+        max: f64,
+        bits: u32,
+    },
+    /// The functions in the named module.  We can't see inside these, so the module's path is
+    /// all that makes it into the fingerprint.
+    With(&'static str),
+}
+
+/// A single enum variant.
+#[derive(Clone)]
+pub struct Variant {
+    pub name: &'static str,
+    /// The tag written ahead of the variant's fields.
+    pub tag: u64,
+    pub fields: Vec<Field>,
+}
+
+/// Hashes `shape` (and everything inside it) with 64-bit FNV-1a.  Unlike `std::hash`, the result
+/// doesn't depend on the platform or compiler version, so it's safe to send over the network.
+///
+/// Structs and enums are identified by name when checking for recursion, so a type containing a
+/// *different* type with the same name will only hash the inner one's name.
+pub fn fingerprint(shape: &Shape) -> u64 {
+    let mut hasher = Fnv1a::new();
+    let mut stack = vec![];
+    hash_shape(shape, &mut hasher, &mut stack);
+    hasher.finish()
+}
+
+fn hash_shape(shape: &Shape, hasher: &mut Fnv1a, stack: &mut Vec<&'static str>) {
+    match *shape {
+        Shape::Primitive(name) => {
+            hasher.write_u8(0);
+            hasher.write_str(name);
+        }
+        Shape::Option(inner) => {
+            hasher.write_u8(1);
+            hash_shape(&inner(), hasher, stack);
+        }
+        Shape::Seq(elem) => {
+            hasher.write_u8(2);
+            hash_shape(&elem(), hasher, stack);
+        }
+        Shape::Map(key, val) => {
+            hasher.write_u8(3);
+            hash_shape(&key(), hasher, stack);
+            hash_shape(&val(), hasher, stack);
+        }
+        Shape::Array(elem, len) => {
+            hasher.write_u8(4);
+            hasher.write_u64(len as u64);
+            hash_shape(&elem(), hasher, stack);
+        }
+        Shape::Tuple(ref elems) => {
+            hasher.write_u8(5);
+            hasher.write_u64(elems.len() as u64);
+            for elem in elems.iter() {
+                hash_shape(&elem(), hasher, stack);
+            }
+        }
+        Shape::Struct { name, ref fields } => {
+            hasher.write_u8(6);
+            hasher.write_str(name);
+            if stack.contains(&name) {
+                return;
+            }
+            stack.push(name);
+            hash_fields(fields, hasher, stack);
+            stack.pop();
+        }
+        Shape::Enum { name, ref variants } => {
+            hasher.write_u8(7);
+            hasher.write_str(name);
+            if stack.contains(&name) {
+                return;
+            }
+            stack.push(name);
+            hasher.write_u64(variants.len() as u64);
+            for variant in variants.iter() {
+                hasher.write_str(variant.name);
+                hasher.write_u64(variant.tag);
+                hash_fields(&variant.fields, hasher, stack);
+            }
+            stack.pop();
+        }
+    }
+}
+
+fn hash_fields(fields: &[Field], hasher: &mut Fnv1a, stack: &mut Vec<&'static str>) {
+    hasher.write_u64(fields.len() as u64);
+    for field in fields.iter() {
+        hasher.write_str(field.name);
+        match field.since {
+            Some(since) => {
+                hasher.write_u8(1);
+                hasher.write_u64(u64::from(since));
+            }
+            None => hasher.write_u8(0),
+        }
+        match field.encoding {
+            Encoding::Default(schema) => {
+                hasher.write_u8(0);
+                hash_shape(&schema(), hasher, stack);
+            }
+            Encoding::Varint(schema) => {
+                hasher.write_u8(1);
+                hash_shape(&schema(), hasher, stack);
+            }
+            Encoding::With(path) => {
+                hasher.write_u8(2);
+                hasher.write_str(path);
+            }
+            Encoding::Bits(schema, bits) => {
+                hasher.write_u8(3);
+                hasher.write_u64(u64::from(bits));
+                hash_shape(&schema(), hasher, stack);
+            }
+            Encoding::Quantized {
+                schema,
+                min,
+                max,
+                bits,
+            } => {
+                hasher.write_u8(4);
+                hasher.write_u64(min.to_bits());
+                hasher.write_u64(max.to_bits());
+                hasher.write_u64(u64::from(bits));
+                hash_shape(&schema(), hasher, stack);
+            }
+        }
+    }
+}
+
+struct Fnv1a(u64);
+
+impl Fnv1a {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    fn new() -> Self {
+        Fnv1a(Self::OFFSET_BASIS)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= u64::from(byte);
+            self.0 = self.0.wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u8(&mut self, val: u8) {
+        self.write(&[val]);
+    }
+
+    fn write_u64(&mut self, val: u64) {
+        self.write(&val.to_be_bytes());
+    }
+
+    // Strings are length-prefixed, so neighbouring names can't run into each other.
+    fn write_str(&mut self, val: &str) {
+        self.write_u64(val.len() as u64);
+        self.write(val.as_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+macro_rules! impl_primitive {
+    ( $( $ty: ty ),* ) => {
+        $(
+            impl Schema for $ty {
+                fn schema() -> Shape {
+                    Shape::Primitive(stringify!($ty))
+                }
+            }
+        )*
+    };
+}
+
+impl_primitive!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, bool, usize, String);
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Shape {
+        Shape::Option(T::schema)
+    }
+}
+
+// Boxes are written as whatever's inside them.
+impl<T: Schema + ?Sized> Schema for Box<T> {
+    fn schema() -> Shape {
+        T::schema()
+    }
+}
+
+// Borrowed strings and slices are written the same way as their owned counterparts.
+
+impl Schema for str {
+    fn schema() -> Shape {
+        String::schema()
+    }
+}
+
+impl<T: Schema> Schema for [T] {
+    fn schema() -> Shape {
+        Vec::<T>::schema()
+    }
+}
+
+impl<T: Schema + ?Sized> Schema for &T {
+    fn schema() -> Shape {
+        T::schema()
+    }
+}
+
+impl<T: ?Sized> Schema for PhantomData<T> {
+    fn schema() -> Shape {
+        Shape::Tuple(vec![])
+    }
+}
+
+impl<T: Schema, const N: usize> Schema for [T; N] {
+    fn schema() -> Shape {
+        Shape::Array(T::schema, N)
+    }
+}
+
+macro_rules! impl_tuple {
+    ( $( $name: ident ),* ) => {
+        impl<$( $name: Schema ),*> Schema for ( $( $name, )* ) {
+            fn schema() -> Shape {
+                Shape::Tuple(vec![ $( $name::schema ),* ])
+            }
+        }
+    };
+}
+
+impl_tuple!();
+impl_tuple!(T0);
+impl_tuple!(T0, T1);
+impl_tuple!(T0, T1, T2);
+impl_tuple!(T0, T1, T2, T3);
+impl_tuple!(T0, T1, T2, T3, T4);
+impl_tuple!(T0, T1, T2, T3, T4, T5);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6, T7);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+impl_tuple!(T0, T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+
+// Sets are written the same way as `Vec`s, and the two kinds of maps are written the same way as
+// each other, so they share schemas.
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Shape {
+        Shape::Seq(T::schema)
+    }
+}
+
+impl<T: Schema> Schema for VecDeque<T> {
+    fn schema() -> Shape {
+        Shape::Seq(T::schema)
+    }
+}
+
+impl<T: Schema, H> Schema for HashSet<T, H> {
+    fn schema() -> Shape {
+        Shape::Seq(T::schema)
+    }
+}
+
+impl<T: Schema> Schema for BTreeSet<T> {
+    fn schema() -> Shape {
+        Shape::Seq(T::schema)
+    }
+}
+
+impl<K: Schema, V: Schema, H> Schema for HashMap<K, V, H> {
+    fn schema() -> Shape {
+        Shape::Map(K::schema, V::schema)
+    }
+}
+
+impl<K: Schema, V: Schema> Schema for BTreeMap<K, V> {
+    fn schema() -> Shape {
+        Shape::Map(K::schema, V::schema)
+    }
+}
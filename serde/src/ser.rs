@@ -0,0 +1,282 @@
+use std::convert::TryFrom;
+
+use crate::config::write_uint;
+use crate::{Config, StructKind, UsizeWidth, LATEST_VERSION};
+
+/// A sink that serialized values are streamed into.
+///
+/// Only `write_bytes` has to be implemented.  Every other method has a default that writes the
+/// binary wire format in terms of it, so formats that aren't made of raw bytes (like
+/// `text::TextWriter`) override the rest to see the structure of what's being written.
+pub trait Serializer {
+    /// Appends `bytes` to the output.
+    fn write_bytes(&mut self, bytes: &[u8]);
+
+    /// The protocol version being written.  Fields marked `#[serde(since = N)]` are left out if
+    /// this is older than `N`.
+    fn version(&self) -> u32 {
+        LATEST_VERSION
+    }
+
+    /// The options for the binary format being written (see `config::Config`).
+    fn config(&self) -> Config {
+        Config::default()
+    }
+
+    /// Returns true if this is a text format, in which case compact encodings like `Varint` don't
+    /// apply.
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    // Primitives
+
+    fn write_u8(&mut self, val: u8) {
+        self.write_bytes(&[val]);
+    }
+
+    fn write_u16(&mut self, val: u16) {
+        write_uint(self, u64::from(val), 2);
+    }
+
+    fn write_u32(&mut self, val: u32) {
+        write_uint(self, u64::from(val), 4);
+    }
+
+    fn write_u64(&mut self, val: u64) {
+        write_uint(self, val, 8);
+    }
+
+    // Signed integers and floats are written as the unsigned integers with the same bits.
+
+    fn write_i8(&mut self, val: i8) {
+        self.write_u8(val as u8);
+    }
+
+    fn write_i16(&mut self, val: i16) {
+        self.write_u16(val as u16);
+    }
+
+    fn write_i32(&mut self, val: i32) {
+        self.write_u32(val as u32);
+    }
+
+    fn write_i64(&mut self, val: i64) {
+        self.write_u64(val as u64);
+    }
+
+    fn write_f32(&mut self, val: f32) {
+        self.write_u32(val.to_bits());
+    }
+
+    fn write_f64(&mut self, val: f64) {
+        self.write_u64(val.to_bits());
+    }
+
+    fn write_bool(&mut self, val: bool) {
+        self.write_u8(val as u8);
+    }
+
+    /// Writes `val` in the configured width.  Panics if it doesn't fit, rather than quietly
+    /// writing the wrong length.
+    fn write_usize(&mut self, val: usize) {
+        match self.config().usize_width {
+            UsizeWidth::U32 => match u32::try_from(val) {
+                Ok(val) => self.write_u32(val),
+                Err(_) => panic!("{} doesn't fit in a 4-byte `usize`", val),
+            },
+            UsizeWidth::U64 => self.write_u64(val as u64),
+        }
+    }
+
+    fn write_str(&mut self, val: &str) {
+        self.write_usize(val.len());
+        self.write_bytes(val.as_bytes());
+    }
+
+    /// Writes the low `bits` bits of `val` (see `bits::Bits`).  Only `bits::BitWriter` can write
+    /// part of a byte, so the other formats round up to a whole number of bytes.
+    fn write_bits(&mut self, val: u64, bits: u32) {
+        write_uint(self, val, bits.div_ceil(8) as usize);
+    }
+
+    // Structure
+
+    /// Starts a struct, or the fields of an enum variant (after `write_variant_tag`).  Each field
+    /// is preceded by a call to `begin_field`.
+    fn begin_struct(&mut self, _name: &'static str, _kind: StructKind) {}
+
+    fn begin_field(&mut self, _name: &'static str) {}
+
+    fn end_struct(&mut self) {}
+
+    /// Identifies which variant of an enum follows.  The binary format only has room for the
+    /// `tag`, which is written in `tag_len` bytes, or `tag_bits` bits when bit-packed.
+    fn write_variant_tag(
+        &mut self,
+        _enum_name: &'static str,
+        _variant_name: &'static str,
+        tag: u64,
+        tag_len: usize,
+        _tag_bits: u32,
+    ) {
+        write_uint(self, tag, tag_len);
+    }
+
+    fn write_none(&mut self) {
+        self.write_u8(0);
+    }
+
+    /// Starts an `Option` that holds a value, which is followed by a call to `end_some`.
+    fn begin_some(&mut self) {
+        self.write_u8(1);
+    }
+
+    fn end_some(&mut self) {}
+
+    /// Starts a sequence of `len` elements.
+    fn begin_seq(&mut self, len: usize) {
+        self.write_usize(len);
+    }
+
+    fn end_seq(&mut self) {}
+
+    /// Starts a map of `len` entries, each written as a key followed by its value.
+    fn begin_map(&mut self, len: usize) {
+        self.write_usize(len);
+    }
+
+    fn end_map(&mut self) {}
+
+    /// Starts a tuple or array.  These always have `len` elements, so there's no length prefix.
+    fn begin_tuple(&mut self, _len: usize) {}
+
+    fn end_tuple(&mut self) {}
+}
+
+impl Serializer for Vec<u8> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.extend_from_slice(bytes);
+    }
+}
+
+/// A `Serializer` that writes into a fixed-size buffer, without allocating.
+///
+/// Bytes that don't fit in the buffer are dropped, but are still counted by `len`, so callers can
+/// check whether the buffer was large enough after the fact.
+pub struct SliceWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+    version: u32,
+    config: Config,
+}
+
+impl<'a> SliceWriter<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self {
+            buf,
+            len: 0,
+            version: LATEST_VERSION,
+            config: Config::default(),
+        }
+    }
+
+    /// Writes in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Writes with the given options, instead of the default network format.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Returns the number of bytes that have been written (including any that didn't fit).
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns true if more bytes were written than the buffer could hold.
+    pub fn overflowed(&self) -> bool {
+        self.len > self.buf.len()
+    }
+}
+
+impl<'a> Serializer for SliceWriter<'a> {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.len < self.buf.len() {
+            let dest = &mut self.buf[self.len..];
+            let amt = dest.len().min(bytes.len());
+            dest[..amt].copy_from_slice(&bytes[..amt]);
+        }
+        self.len += bytes.len();
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn config(&self) -> Config {
+        self.config
+    }
+}
+
+/// A `Serializer` that appends to a vector of bytes, like `Vec<u8>` itself, but in any protocol
+/// version or config.
+pub struct VecWriter {
+    out: Vec<u8>,
+    version: u32,
+    config: Config,
+}
+
+impl VecWriter {
+    pub fn new() -> Self {
+        Self {
+            out: vec![],
+            version: LATEST_VERSION,
+            config: Config::default(),
+        }
+    }
+
+    /// Writes in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Writes with the given options, instead of the default network format.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+impl Default for VecWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Serializer for VecWriter {
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.out.extend_from_slice(bytes);
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn config(&self) -> Config {
+        self.config
+    }
+}
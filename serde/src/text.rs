@@ -0,0 +1,875 @@
+//! A human-readable text format, for save files, config and debug dumps.
+//!
+//! It's written and read by the same `Serialize`/`Deserialize` impls as the binary format, and
+//! looks a lot like Rust (or RON, if you've seen it):
+//!
+//! ```text
+//! RenderComponent(
+//!     color: (0.3, 0.7, 0.3, 1.0),
+//!     size: 20.0,
+//! )
+//! ```
+//!
+//! Structs and enum variants are written by name, with their fields in parentheses.  Tuples and
+//! arrays are `(a, b)`, other sequences are `[a, b]`, maps are `{key: value}`, and options are
+//! `Some(x)` or `None`.  When reading, struct names are optional, trailing commas are allowed, and
+//! `//` starts a comment.  Fields have to appear in the order they're declared in.
+
+use std::fmt::Write;
+use std::mem;
+
+use crate::de::check_len;
+use crate::{bits, DEFAULT_MAX_LEN, LATEST_VERSION};
+use crate::{Deserialize, DeserializeError, Deserializer, Serialize, Serializer, StructKind};
+
+/// Serializes `val` to a string.
+pub fn to_string<T: Serialize + ?Sized>(val: &T) -> String {
+    let mut writer = TextWriter::new();
+    val.serialize_into(&mut writer);
+    writer.into_string()
+}
+
+/// Deserializes a `T` from `text`, which must contain nothing else (besides whitespace and
+/// comments).
+pub fn from_str<'a, T: Deserialize<'a>>(text: &'a str) -> Result<T, DeserializeError> {
+    let mut reader = TextReader::new(text);
+    let result = T::deserialize_from(&mut reader)?;
+    reader.finish()?;
+    Ok(result)
+}
+
+const INDENT: &str = "    ";
+
+/// An open struct, sequence, or other container.
+struct Frame {
+    /// The character that closes the container, if there is one (unit structs don't have one).
+    close: Option<char>,
+    /// Whether each item goes on its own line.
+    multiline: bool,
+    /// Whether items are written `name: value` (for structs with named fields).
+    named: bool,
+    /// Whether items alternate between keys and values.
+    map: bool,
+    /// How many items (map keys and values counted separately) have been started.
+    count: usize,
+}
+
+impl Frame {
+    fn new(close: Option<char>, multiline: bool) -> Self {
+        Self {
+            close,
+            multiline,
+            named: false,
+            map: false,
+            count: 0,
+        }
+    }
+}
+
+/// A `Serializer` that writes the text format into a `String`.
+pub struct TextWriter {
+    out: String,
+    stack: Vec<Frame>,
+    /// Set once whatever comes before a value (a separator, a field name, etc.) has already been
+    /// written, so the value doesn't write its own.
+    value_started: bool,
+    version: u32,
+}
+
+impl TextWriter {
+    pub fn new() -> Self {
+        Self {
+            out: String::new(),
+            stack: vec![],
+            value_started: false,
+            version: LATEST_VERSION,
+        }
+    }
+
+    /// Writes in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.out
+    }
+
+    pub fn into_string(self) -> String {
+        self.out
+    }
+
+    /// Writes whatever needs to come between the previous value and the next one.
+    fn before_value(&mut self) {
+        if mem::replace(&mut self.value_started, false) {
+            return;
+        }
+        let depth = self.stack.len();
+        if let Some(frame) = self.stack.last_mut() {
+            if frame.map && frame.count % 2 == 1 {
+                self.out.push_str(": ");
+                frame.count += 1;
+                return;
+            }
+            Self::separate(&mut self.out, frame, depth);
+        }
+    }
+
+    /// Writes the separator before the next item of `frame`, which is `depth` levels deep.
+    fn separate(out: &mut String, frame: &mut Frame, depth: usize) {
+        if frame.count > 0 {
+            out.push(',');
+            if !frame.multiline {
+                out.push(' ');
+            }
+        }
+        frame.count += 1;
+        if frame.multiline {
+            newline(out, depth);
+        }
+    }
+
+    fn open(&mut self, text: &str, frame: Frame) {
+        self.before_value();
+        self.out.push_str(text);
+        self.stack.push(frame);
+    }
+
+    fn close(&mut self) {
+        let frame = self
+            .stack
+            .pop()
+            .expect("closed a container that was never opened");
+        if frame.multiline && frame.count > 0 {
+            self.out.push(',');
+            newline(&mut self.out, self.stack.len());
+        }
+        if let Some(close) = frame.close {
+            self.out.push(close);
+        }
+    }
+
+    fn write_display<T: std::fmt::Display>(&mut self, val: T) {
+        self.before_value();
+        write!(self.out, "{}", val).unwrap();
+    }
+
+    fn write_float<T: std::fmt::Debug>(&mut self, val: T) {
+        // `Debug` always includes a decimal point or exponent, and round-trips exactly.
+        self.before_value();
+        write!(self.out, "{:?}", val).unwrap();
+    }
+}
+
+impl Default for TextWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn newline(out: &mut String, depth: usize) {
+    out.push('\n');
+    for _ in 0..depth {
+        out.push_str(INDENT);
+    }
+}
+
+impl Serializer for TextWriter {
+    /// Raw bytes (from hand-written impls) are written as a byte string literal, like `b"\x01A"`.
+    /// They have to be read back with the same sequence of `read_bytes` calls.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        self.before_value();
+        self.out.push_str("b\"");
+        for &byte in bytes {
+            match byte {
+                b'"' => self.out.push_str("\\\""),
+                b'\\' => self.out.push_str("\\\\"),
+                0x20..=0x7e => self.out.push(byte as char),
+                _ => write!(self.out, "\\x{:02x}", byte).unwrap(),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    fn write_u8(&mut self, val: u8) {
+        self.write_display(val);
+    }
+
+    fn write_u16(&mut self, val: u16) {
+        self.write_display(val);
+    }
+
+    fn write_u32(&mut self, val: u32) {
+        self.write_display(val);
+    }
+
+    fn write_u64(&mut self, val: u64) {
+        self.write_display(val);
+    }
+
+    fn write_i8(&mut self, val: i8) {
+        self.write_display(val);
+    }
+
+    fn write_i16(&mut self, val: i16) {
+        self.write_display(val);
+    }
+
+    fn write_i32(&mut self, val: i32) {
+        self.write_display(val);
+    }
+
+    fn write_i64(&mut self, val: i64) {
+        self.write_display(val);
+    }
+
+    fn write_f32(&mut self, val: f32) {
+        self.write_float(val);
+    }
+
+    fn write_f64(&mut self, val: f64) {
+        self.write_float(val);
+    }
+
+    fn write_bool(&mut self, val: bool) {
+        self.write_display(val);
+    }
+
+    fn write_usize(&mut self, val: usize) {
+        self.write_display(val);
+    }
+
+    fn write_str(&mut self, val: &str) {
+        self.before_value();
+        self.out.push('"');
+        for c in val.chars() {
+            match c {
+                '"' => self.out.push_str("\\\""),
+                '\\' => self.out.push_str("\\\\"),
+                '\n' => self.out.push_str("\\n"),
+                '\r' => self.out.push_str("\\r"),
+                '\t' => self.out.push_str("\\t"),
+                c if c.is_control() => write!(self.out, "\\u{{{:x}}}", c as u32).unwrap(),
+                c => self.out.push(c),
+            }
+        }
+        self.out.push('"');
+    }
+
+    fn write_bits(&mut self, val: u64, _bits: u32) {
+        self.write_display(val);
+    }
+
+    fn begin_struct(&mut self, name: &'static str, kind: StructKind) {
+        let frame = match kind {
+            StructKind::Named => Frame {
+                named: true,
+                ..Frame::new(Some(')'), true)
+            },
+            StructKind::Tuple => Frame::new(Some(')'), false),
+            StructKind::Unit => Frame::new(None, false),
+        };
+        self.before_value();
+        self.out.push_str(name);
+        if kind != StructKind::Unit {
+            self.out.push('(');
+        }
+        self.stack.push(frame);
+    }
+
+    fn begin_field(&mut self, name: &'static str) {
+        let depth = self.stack.len();
+        match self.stack.last_mut() {
+            Some(frame) if frame.named => {
+                Self::separate(&mut self.out, frame, depth);
+                self.out.push_str(name);
+                self.out.push_str(": ");
+                self.value_started = true;
+            }
+            // Tuple fields don't have names, so they're written like any other value.
+            _ => (),
+        }
+    }
+
+    fn end_struct(&mut self) {
+        self.close();
+    }
+
+    fn write_variant_tag(
+        &mut self,
+        _enum_name: &'static str,
+        _variant_name: &'static str,
+        _tag: u64,
+        _tag_len: usize,
+        _tag_bits: u32,
+    ) {
+        // The variant's name is written by the `begin_struct` call that follows.
+    }
+
+    fn write_none(&mut self) {
+        self.before_value();
+        self.out.push_str("None");
+    }
+
+    fn begin_some(&mut self) {
+        self.open("Some(", Frame::new(Some(')'), false));
+    }
+
+    fn end_some(&mut self) {
+        self.close();
+    }
+
+    fn begin_seq(&mut self, _len: usize) {
+        self.open("[", Frame::new(Some(']'), true));
+    }
+
+    fn end_seq(&mut self) {
+        self.close();
+    }
+
+    fn begin_map(&mut self, _len: usize) {
+        let frame = Frame {
+            map: true,
+            ..Frame::new(Some('}'), true)
+        };
+        self.open("{", frame);
+    }
+
+    fn end_map(&mut self) {
+        self.close();
+    }
+
+    fn begin_tuple(&mut self, _len: usize) {
+        self.open("(", Frame::new(Some(')'), false));
+    }
+
+    fn end_tuple(&mut self) {
+        self.close();
+    }
+}
+
+/// A `Deserializer` that reads the text format.
+pub struct TextReader<'a> {
+    input: &'a str,
+    pos: usize,
+    stack: Vec<Frame>,
+    /// Set once whatever comes before a value (a separator, a field name, etc.) has already been
+    /// read, so reading the value doesn't expect it again.
+    value_started: bool,
+    version: u32,
+    max_len: usize,
+    /// Holds the output of `read_bytes`, which has to be unescaped.
+    scratch: Vec<u8>,
+}
+
+impl<'a> TextReader<'a> {
+    pub fn new(input: &'a str) -> Self {
+        Self {
+            input,
+            pos: 0,
+            stack: vec![],
+            value_started: false,
+            version: LATEST_VERSION,
+            max_len: DEFAULT_MAX_LEN,
+            scratch: vec![],
+        }
+    }
+
+    /// Reads input that was written in the format of an older protocol version.
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// Replaces `DEFAULT_MAX_LEN` as the limit on the length of sequences, maps and strings.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self
+    }
+
+    /// Checks that there's nothing but whitespace and comments left.
+    pub fn finish(mut self) -> Result<(), DeserializeError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("expected end of input")),
+        }
+    }
+
+    fn error<M: Into<String>>(&self, message: M) -> DeserializeError {
+        let consumed = &self.input[..self.pos];
+        let line = consumed.matches('\n').count() + 1;
+        let line_start = consumed.rfind('\n').map_or(0, |i| i + 1);
+        DeserializeError::Syntax {
+            line,
+            column: consumed[line_start..].chars().count() + 1,
+            message: message.into(),
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Skips whitespace, then consumes `c` if it's next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), DeserializeError> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{}`", c)))
+        }
+    }
+
+    /// Returns the identifier at the front of the input without consuming it, if there is one.
+    fn peek_ident(&mut self) -> Option<&'a str> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        match rest.chars().next() {
+            Some(c) if c.is_alphabetic() || c == '_' => (),
+            _ => return None,
+        }
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        Some(&rest[..len])
+    }
+
+    fn expect_ident(&mut self, expected: &str) -> Result<(), DeserializeError> {
+        match self.peek_ident() {
+            Some(ident) if ident == expected => {
+                self.pos += ident.len();
+                Ok(())
+            }
+            Some(ident) => Err(self.error(format!("expected `{}`, found `{}`", expected, ident))),
+            None => Err(self.error(format!("expected `{}`", expected))),
+        }
+    }
+
+    /// Reads whatever needs to come between the previous value and the next one.
+    fn before_value(&mut self) -> Result<(), DeserializeError> {
+        if mem::replace(&mut self.value_started, false) {
+            return Ok(());
+        }
+        let (is_value, needs_comma) = match self.stack.last_mut() {
+            Some(frame) => {
+                let is_value = frame.map && frame.count % 2 == 1;
+                let needs_comma = !is_value && frame.count > 0;
+                frame.count += 1;
+                (is_value, needs_comma)
+            }
+            None => (false, false),
+        };
+        if is_value {
+            self.expect(':')
+        } else if needs_comma {
+            self.expect(',')
+        } else {
+            Ok(())
+        }
+    }
+
+    fn open(&mut self, c: char, frame: Frame) -> Result<(), DeserializeError> {
+        self.before_value()?;
+        self.expect(c)?;
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), DeserializeError> {
+        let frame = self
+            .stack
+            .pop()
+            .expect("closed a container that was never opened");
+        if let Some(close) = frame.close {
+            // Allow a trailing comma.
+            if frame.count > 0 {
+                self.eat(',');
+            }
+            self.expect(close)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a number (or anything else without spaces or punctuation) and parses it.
+    fn read_token<T: std::str::FromStr>(&mut self, type_name: &str) -> Result<T, DeserializeError> {
+        self.before_value()?;
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '.' || c == '-' || c == '+'))
+            .unwrap_or(rest.len());
+        let token = &rest[..len];
+        match token.parse() {
+            Ok(val) => {
+                self.pos += len;
+                Ok(val)
+            }
+            Err(_) if token.is_empty() => Err(self.error(format!("expected a `{}`", type_name))),
+            Err(_) => Err(self.error(format!("invalid `{}`: `{}`", type_name, token))),
+        }
+    }
+
+    /// Reads the contents of a quoted string, after the opening quote, unescaping it into `out`.
+    /// Returns an error if the string isn't closed, or if `push` rejects a character.
+    fn read_quoted<F>(&mut self, mut push: F) -> Result<(), DeserializeError>
+    where
+        F: FnMut(char) -> bool,
+    {
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(self.error("unterminated string")),
+            };
+            self.pos += c.len_utf8();
+            let unescaped = match c {
+                '"' => return Ok(()),
+                '\\' => self.read_escape()?,
+                c => c,
+            };
+            if !push(unescaped) {
+                return Err(self.error("invalid character in byte string"));
+            }
+        }
+    }
+
+    fn read_escape(&mut self) -> Result<char, DeserializeError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error("unterminated string"))?;
+        self.pos += c.len_utf8();
+        match c {
+            '"' => Ok('"'),
+            '\\' => Ok('\\'),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            'x' => {
+                let hex = self.rest().get(..2).unwrap_or("");
+                let val =
+                    u8::from_str_radix(hex, 16).map_err(|_| self.error("invalid `\\x` escape"))?;
+                self.pos += 2;
+                Ok(val as char)
+            }
+            'u' => {
+                self.expect('{')?;
+                let rest = self.rest();
+                let len = rest.find('}').unwrap_or(0);
+                let val = u32::from_str_radix(&rest[..len], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| self.error("invalid `\\u` escape"))?;
+                self.pos += len;
+                self.expect('}')?;
+                Ok(val)
+            }
+            _ => Err(self.error(format!("unknown escape `\\{}`", c))),
+        }
+    }
+}
+
+impl<'a> Deserializer<'a> for TextReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Result<&[u8], DeserializeError> {
+        self.before_value()?;
+        self.expect_ident("b")?;
+        if self.peek() != Some('"') {
+            return Err(self.error("expected a byte string"));
+        }
+        self.pos += 1;
+        let mut scratch = mem::take(&mut self.scratch);
+        scratch.clear();
+        let result = self.read_quoted(|c| {
+            let ok = (c as u32) <= 0xff;
+            scratch.push(c as u8);
+            ok
+        });
+        self.scratch = scratch;
+        result?;
+        if self.scratch.len() != len {
+            return Err(self.error(format!(
+                "expected {} bytes, found {}",
+                len,
+                self.scratch.len()
+            )));
+        }
+        Ok(&self.scratch)
+    }
+
+    fn version(&self) -> u32 {
+        self.version
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    fn is_human_readable(&self) -> bool {
+        true
+    }
+
+    fn read_u8(&mut self) -> Result<u8, DeserializeError> {
+        self.read_token("u8")
+    }
+
+    fn read_u16(&mut self) -> Result<u16, DeserializeError> {
+        self.read_token("u16")
+    }
+
+    fn read_u32(&mut self) -> Result<u32, DeserializeError> {
+        self.read_token("u32")
+    }
+
+    fn read_u64(&mut self) -> Result<u64, DeserializeError> {
+        self.read_token("u64")
+    }
+
+    fn read_i8(&mut self) -> Result<i8, DeserializeError> {
+        self.read_token("i8")
+    }
+
+    fn read_i16(&mut self) -> Result<i16, DeserializeError> {
+        self.read_token("i16")
+    }
+
+    fn read_i32(&mut self) -> Result<i32, DeserializeError> {
+        self.read_token("i32")
+    }
+
+    fn read_i64(&mut self) -> Result<i64, DeserializeError> {
+        self.read_token("i64")
+    }
+
+    fn read_f32(&mut self) -> Result<f32, DeserializeError> {
+        self.read_token("f32")
+    }
+
+    fn read_f64(&mut self) -> Result<f64, DeserializeError> {
+        self.read_token("f64")
+    }
+
+    fn read_bool(&mut self) -> Result<bool, DeserializeError> {
+        self.read_token("bool")
+    }
+
+    fn read_usize(&mut self) -> Result<usize, DeserializeError> {
+        self.read_token("usize")
+    }
+
+    // There's no length prefix to check up front, but the string can't be any longer than the
+    // input it came from.
+    fn read_string(&mut self, max_len: usize) -> Result<String, DeserializeError> {
+        self.before_value()?;
+        self.expect('"')?;
+        let mut result = String::new();
+        self.read_quoted(|c| {
+            result.push(c);
+            true
+        })?;
+        check_len(result.len(), max_len)?;
+        Ok(result)
+    }
+
+    /// Only works for strings without escapes, since those would have to be unescaped into a
+    /// copy.
+    fn read_borrowed_str(&mut self, max_len: usize) -> Result<&'a str, DeserializeError> {
+        self.before_value()?;
+        self.expect('"')?;
+        let rest = self.rest();
+        match rest.find(['"', '\\']) {
+            Some(len) if rest[len..].starts_with('"') => {
+                check_len(len, max_len)?;
+                self.pos += len + 1;
+                Ok(&rest[..len])
+            }
+            Some(_) => Err(DeserializeError::CannotBorrow),
+            None => Err(self.error("unterminated string")),
+        }
+    }
+
+    fn read_bits(&mut self, bits: u32) -> Result<u64, DeserializeError> {
+        let val = self.read_token("u64")?;
+        if val & !bits::mask(bits) != 0 {
+            return Err(self.error(format!("{} doesn't fit in {} bits", val, bits)));
+        }
+        Ok(val)
+    }
+
+    fn begin_struct(
+        &mut self,
+        name: &'static str,
+        kind: StructKind,
+    ) -> Result<(), DeserializeError> {
+        self.before_value()?;
+        // The name can be left out, unless it's all there is.
+        if kind == StructKind::Unit || self.peek_ident().is_some() {
+            self.expect_ident(name)?;
+        }
+        let frame = match kind {
+            StructKind::Named => Frame {
+                named: true,
+                ..Frame::new(Some(')'), true)
+            },
+            StructKind::Tuple => Frame::new(Some(')'), false),
+            StructKind::Unit => Frame::new(None, false),
+        };
+        if kind != StructKind::Unit {
+            self.expect('(')?;
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn begin_field(&mut self, name: &'static str) -> Result<(), DeserializeError> {
+        let needs_comma = match self.stack.last_mut() {
+            Some(frame) if frame.named => {
+                frame.count += 1;
+                frame.count > 1
+            }
+            _ => return Ok(()),
+        };
+        if needs_comma {
+            self.expect(',')?;
+        }
+        match self.peek_ident() {
+            Some(ident) if ident == name => self.pos += ident.len(),
+            Some(ident) => {
+                return Err(self.error(format!("expected field `{}`, found `{}`", name, ident)))
+            }
+            None => return Err(self.error(format!("expected field `{}`", name))),
+        }
+        self.expect(':')?;
+        self.value_started = true;
+        Ok(())
+    }
+
+    fn end_struct(&mut self) -> Result<(), DeserializeError> {
+        self.close()
+    }
+
+    fn read_variant_tag(
+        &mut self,
+        enum_name: &'static str,
+        variants: &[(&'static str, u64)],
+        _tag_len: usize,
+        _tag_bits: u32,
+    ) -> Result<u64, DeserializeError> {
+        self.before_value()?;
+        // The variant's name gets read again by `begin_struct`.
+        self.value_started = true;
+        let ident = match self.peek_ident() {
+            Some(ident) => ident,
+            None => return Err(self.error(format!("expected a variant of `{}`", enum_name))),
+        };
+        match variants.iter().find(|&&(name, _)| name == ident) {
+            Some(&(_, tag)) => Ok(tag),
+            None => Err(self.error(format!("`{}` isn't a variant of `{}`", ident, enum_name))),
+        }
+    }
+
+    fn read_option(&mut self) -> Result<bool, DeserializeError> {
+        self.before_value()?;
+        match self.peek_ident() {
+            Some("None") => {
+                self.pos += "None".len();
+                Ok(false)
+            }
+            Some("Some") => {
+                self.pos += "Some".len();
+                self.expect('(')?;
+                self.stack.push(Frame::new(Some(')'), false));
+                Ok(true)
+            }
+            _ => Err(self.error("expected `Some(...)` or `None`")),
+        }
+    }
+
+    fn end_some(&mut self) -> Result<(), DeserializeError> {
+        self.close()
+    }
+
+    fn begin_seq(&mut self) -> Result<Option<usize>, DeserializeError> {
+        self.open('[', Frame::new(Some(']'), true))?;
+        Ok(None)
+    }
+
+    fn next_elem(&mut self) -> Result<bool, DeserializeError> {
+        let (count, close) = match self.stack.last() {
+            Some(frame) => (frame.count, frame.close),
+            None => return Ok(false),
+        };
+        if count > 0 && !self.eat(',') {
+            // Without a comma, the only thing that can come next is the end of the container.
+            return Ok(false);
+        }
+        self.skip_whitespace();
+        if self.peek().is_some() && self.peek() == close {
+            return Ok(false);
+        }
+        if let Some(frame) = self.stack.last_mut() {
+            frame.count += 1;
+        }
+        self.value_started = true;
+        Ok(true)
+    }
+
+    fn end_seq(&mut self) -> Result<(), DeserializeError> {
+        self.close()
+    }
+
+    fn begin_map(&mut self) -> Result<Option<usize>, DeserializeError> {
+        let frame = Frame {
+            map: true,
+            ..Frame::new(Some('}'), true)
+        };
+        self.open('{', frame)?;
+        Ok(None)
+    }
+
+    fn end_map(&mut self) -> Result<(), DeserializeError> {
+        self.close()
+    }
+
+    fn begin_tuple(&mut self, _len: usize) -> Result<(), DeserializeError> {
+        self.open('(', Frame::new(Some(')'), false))
+    }
+
+    fn end_tuple(&mut self) -> Result<(), DeserializeError> {
+        self.close()
+    }
+}
@@ -0,0 +1,191 @@
+//! Variable-length integer encoding.
+//!
+//! Unsigned values are written as LEB128: seven bits per byte, least significant group first, with
+//! the high bit of each byte set if more bytes follow.  Signed values are zigzag-encoded first, so
+//! that small negative numbers stay small (0 => 0, -1 => 1, 1 => 2, -2 => 3, ...).
+
+use std::convert::TryFrom;
+
+use crate::de::check_len;
+use crate::{Deserialize, DeserializeError, DeserializeOwned, Deserializer, Serialize, Serializer};
+
+/// The most bytes a LEB128-encoded `u64` can take up.
+pub const MAX_VARINT_LEN: usize = 10;
+
+/// Types with a variable-length encoding, for use with `#[serde(varint)]` fields.
+///
+/// Human-readable formats have no use for the compact encoding, so they get the type's usual
+/// `Serialize`/`Deserialize` representation instead, as do binary formats with varints turned off
+/// in their `Config`.
+pub trait Varint: Sized {
+    /// Streams the variable-length form of the value into `out`.
+    fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S);
+
+    /// Attempts to read a value written by `serialize_varint` from the front of `de`.
+    fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError>;
+}
+
+/// Writes `val` as an unsigned LEB128 varint.
+pub fn write_u64<S: Serializer + ?Sized>(out: &mut S, mut val: u64) {
+    let mut buf = [0u8; MAX_VARINT_LEN];
+    let mut len = 0;
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            buf[len] = byte;
+            len += 1;
+            break;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+    out.write_bytes(&buf[..len]);
+}
+
+/// Reads an unsigned LEB128 varint.  Fails if the encoding is longer than any `u64` needs.
+pub fn read_u64<'de, D: Deserializer<'de> + ?Sized>(de: &mut D) -> Result<u64, DeserializeError> {
+    let mut result = 0u64;
+    for i in 0..MAX_VARINT_LEN {
+        let byte = de.read_bytes(1)?[0];
+        let bits = u64::from(byte & 0x7f);
+        // The tenth byte only has room for the single remaining bit of a `u64`.
+        if i == MAX_VARINT_LEN - 1 && bits > 1 {
+            return Err(DeserializeError::InvalidVarint);
+        }
+        result |= bits << (7 * i);
+        if byte & 0x80 == 0 {
+            return Ok(result);
+        }
+    }
+    Err(DeserializeError::InvalidVarint)
+}
+
+pub fn zigzag_encode(val: i64) -> u64 {
+    ((val << 1) ^ (val >> 63)) as u64
+}
+
+pub fn zigzag_decode(val: u64) -> i64 {
+    ((val >> 1) as i64) ^ -((val & 1) as i64)
+}
+
+macro_rules! impl_varint_unsigned {
+    ( $ty: ty ) => {
+        impl Varint for $ty {
+            fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S) {
+                if out.is_human_readable() || !out.config().varint {
+                    return self.serialize_into(out);
+                }
+                write_u64(out, *self as u64);
+            }
+
+            fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                if de.is_human_readable() || !de.config().varint {
+                    return Self::deserialize_from(de);
+                }
+                let val = read_u64(de)?;
+                <$ty>::try_from(val).map_err(|_| DeserializeError::InvalidVarint)
+            }
+        }
+    };
+}
+
+impl_varint_unsigned!(u8);
+impl_varint_unsigned!(u16);
+impl_varint_unsigned!(u32);
+impl_varint_unsigned!(u64);
+
+impl Varint for usize {
+    fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S) {
+        if out.is_human_readable() || !out.config().varint {
+            return self.serialize_into(out);
+        }
+        write_u64(out, *self as u64);
+    }
+
+    fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.is_human_readable() || !de.config().varint {
+            return Self::deserialize_from(de);
+        }
+        let val = read_u64(de)?;
+        usize::try_from(val).map_err(|_| DeserializeError::LengthOverflow { len: val })
+    }
+}
+
+macro_rules! impl_varint_signed {
+    ( $ty: ty ) => {
+        impl Varint for $ty {
+            fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S) {
+                if out.is_human_readable() || !out.config().varint {
+                    return self.serialize_into(out);
+                }
+                write_u64(out, zigzag_encode(*self as i64));
+            }
+
+            fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+                de: &mut D,
+            ) -> Result<Self, DeserializeError> {
+                if de.is_human_readable() || !de.config().varint {
+                    return Self::deserialize_from(de);
+                }
+                let val = zigzag_decode(read_u64(de)?);
+                <$ty>::try_from(val).map_err(|_| DeserializeError::InvalidVarint)
+            }
+        }
+    };
+}
+
+impl_varint_signed!(i8);
+impl_varint_signed!(i16);
+impl_varint_signed!(i32);
+impl_varint_signed!(i64);
+
+// For containers, only the length prefix is shortened.  The contents are written as usual.
+
+impl Varint for String {
+    fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S) {
+        if out.is_human_readable() || !out.config().varint {
+            return self.serialize_into(out);
+        }
+        self.len().serialize_varint(out);
+        out.write_bytes(self.as_bytes());
+    }
+
+    fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.is_human_readable() || !de.config().varint {
+            return Self::deserialize_from(de);
+        }
+        let size = check_len(usize::deserialize_varint(de)?, de.max_len())?;
+        crate::read_string(de, size)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Varint for Vec<T> {
+    fn serialize_varint<S: Serializer + ?Sized>(&self, out: &mut S) {
+        if out.is_human_readable() || !out.config().varint {
+            return self.serialize_into(out);
+        }
+        self.len().serialize_varint(out);
+        for val in self.iter() {
+            val.serialize_into(out);
+        }
+    }
+
+    fn deserialize_varint<'de, D: Deserializer<'de> + ?Sized>(
+        de: &mut D,
+    ) -> Result<Self, DeserializeError> {
+        if de.is_human_readable() || !de.config().varint {
+            return Self::deserialize_from(de);
+        }
+        let size = check_len(usize::deserialize_varint(de)?, de.max_len())?;
+        crate::read_vec(de, size)
+    }
+}
@@ -0,0 +1,438 @@
+use syn::punctuated::Punctuated;
+use syn::token::Comma;
+use syn::{
+    Attribute, DeriveInput, Error, Expr, ExprLit, Field, Lit, Meta, MetaList, NestedMeta, Path,
+    Variant, WherePredicate,
+};
+
+use crate::QuoteTokenStream;
+
+/// Options set on the struct/enum itself with `#[serde(...)]`.
+#[derive(Default)]
+pub struct ContainerAttrs {
+    /// Replaces the `T: Serialize` bounds we'd otherwise add for each type parameter.
+    pub ser_bound: Option<Vec<WherePredicate>>,
+    /// Replaces the `T: Deserialize<'de>` bounds we'd otherwise add for each type parameter.
+    pub de_bound: Option<Vec<WherePredicate>>,
+    /// Replaces the `T: Schema` bounds we'd otherwise add for each type parameter.
+    pub schema_bound: Option<Vec<WherePredicate>>,
+    /// Also implement `SerializeDelta`.
+    pub delta: bool,
+    /// Replaces the `T: Serialize + DeserializeOwned + PartialEq` bounds we'd otherwise add to the
+    /// `SerializeDelta` impl for each type parameter.
+    pub delta_bound: Option<Vec<WherePredicate>>,
+}
+
+impl ContainerAttrs {
+    /// Parses the container's `#[serde(...)]` options, collecting an error for each bad one.
+    pub fn parse(ast: &DeriveInput) -> Result<Self, Vec<Error>> {
+        let mut result = Self::default();
+        let mut errors = vec![];
+        for meta in serde_metas(&ast.attrs, &mut errors) {
+            match meta {
+                // `#[serde(bound = "...")]` applies to every impl.
+                Meta::NameValue(ref nv) if nv.ident == "bound" => {
+                    match parse_where_predicates(&nv.lit) {
+                        Ok(bound) => {
+                            result.ser_bound = Some(bound.clone());
+                            result.de_bound = Some(bound.clone());
+                            result.schema_bound = Some(bound.clone());
+                            result.delta_bound = Some(bound);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                // `#[serde(bound(serialize = "...", deserialize = "...", ...))]` sets them
+                // separately.
+                Meta::List(ref list) if list.ident == "bound" => {
+                    for nested in list.nested.iter() {
+                        let (target, lit) = match *nested {
+                            NestedMeta::Meta(Meta::NameValue(ref nv))
+                                if nv.ident == "serialize" =>
+                            {
+                                (&mut result.ser_bound, &nv.lit)
+                            }
+                            NestedMeta::Meta(Meta::NameValue(ref nv))
+                                if nv.ident == "deserialize" =>
+                            {
+                                (&mut result.de_bound, &nv.lit)
+                            }
+                            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "schema" => {
+                                (&mut result.schema_bound, &nv.lit)
+                            }
+                            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "delta" => {
+                                (&mut result.delta_bound, &nv.lit)
+                            }
+                            _ => {
+                                errors.push(Error::new_spanned(
+                                    nested,
+                                    "expected `serialize = \"...\"`, `deserialize = \"...\"`, \
+                                     `schema = \"...\"`, or `delta = \"...\"`",
+                                ));
+                                continue;
+                            }
+                        };
+                        match parse_where_predicates(lit) {
+                            Ok(bound) => *target = Some(bound),
+                            Err(e) => errors.push(e),
+                        }
+                    }
+                }
+                Meta::Word(ref ident) if ident == "delta" => result.delta = true,
+                _ => errors.push(Error::new_spanned(
+                    &meta,
+                    format!("unknown container attribute `{}`", meta.name()),
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(result)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Like `parse`, but for use after `validate` has already reported any errors.
+    pub fn from_ast(ast: &DeriveInput) -> Self {
+        Self::parse(ast).expect("container attributes should've been validated")
+    }
+}
+
+/// Options set on a single enum variant with `#[serde(...)]`.
+#[derive(Default)]
+pub struct VariantAttrs {
+    /// The tag to write for this variant, in place of the one we'd count up to.
+    pub tag: Option<u64>,
+}
+
+impl VariantAttrs {
+    /// Parses the variant's `#[serde(...)]` options, collecting an error for each bad one.
+    pub fn parse(variant: &Variant) -> Result<Self, Vec<Error>> {
+        let mut result = Self::default();
+        let mut errors = vec![];
+        for meta in serde_metas(&variant.attrs, &mut errors) {
+            match meta {
+                Meta::NameValue(ref nv) if nv.ident == "tag" => match nv.lit {
+                    Lit::Int(ref i) => result.tag = Some(i.value()),
+                    _ => errors.push(Error::new_spanned(
+                        &nv.lit,
+                        "expected an integer literal for `tag`",
+                    )),
+                },
+                _ => errors.push(Error::new_spanned(
+                    &meta,
+                    format!("unknown variant attribute `{}`", meta.name()),
+                )),
+            }
+        }
+        if errors.is_empty() {
+            Ok(result)
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+/// Options set on a single field with `#[serde(...)]`.
+#[derive(Default)]
+pub struct FieldAttrs {
+    /// Use the variable-length encoding (`serde::Varint`) for this field.
+    pub varint: bool,
+    /// Never write this field, and fill it in with its default when reading.
+    pub skip: bool,
+    /// A function to call for the field's default value, instead of `Default::default`.
+    pub default: Option<Path>,
+    /// A module with `serialize_into` and `deserialize_from` functions to use for this field,
+    /// instead of the field type's own trait impls.
+    pub with: Option<Path>,
+    /// The protocol version this field was added in.  It's left out when reading or writing older
+    /// versions.
+    pub since: Option<u32>,
+    /// Write this field in this many bits (`serde::Bits`), rather than its usual size.
+    pub bits: Option<u32>,
+    /// Write this field as a fixed-point value (`serde::Quantize`).
+    pub quantize: Option<Quantize>,
+    /// The most elements (or bytes, for strings) the field can have when it's read
+    /// (`serde::MaxLen`), in place of the deserializer's limit.
+    pub max_len: Option<Expr>,
+}
+
+/// The options of `#[serde(quantize(min = ..., max = ..., bits = ...))]`.
+pub struct Quantize {
+    /// The smallest value that can be written.  Anything less gets clamped to it.
+    pub min: Expr,
+    /// The largest value that can be written.  Anything greater gets clamped to it.
+    pub max: Expr,
+    /// How many bits the value is written in.
+    pub bits: u32,
+}
+
+impl FieldAttrs {
+    /// Parses the field's `#[serde(...)]` options, collecting an error for each bad one.
+    pub fn parse(field: &Field) -> Result<Self, Vec<Error>> {
+        let mut result = Self::default();
+        let mut errors = vec![];
+        // The options that replace how the field is encoded (or read), which can't be combined.
+        // `skip` counts too, since a skipped field isn't encoded at all.
+        let mut encoding_metas = vec![];
+        for meta in serde_metas(&field.attrs, &mut errors) {
+            match meta {
+                Meta::Word(ref ident) if ident == "varint" => {
+                    result.varint = true;
+                    encoding_metas.push(meta.clone());
+                }
+                Meta::Word(ref ident) if ident == "skip" => {
+                    result.skip = true;
+                    encoding_metas.push(meta.clone());
+                }
+                // Plain `#[serde(default)]` is the same as not saying anything, but it reads
+                // nicely next to `skip` and `since`.
+                Meta::Word(ref ident) if ident == "default" => (),
+                Meta::NameValue(ref nv) if nv.ident == "default" => match parse_path(&nv.lit) {
+                    Ok(path) => result.default = Some(path),
+                    Err(e) => errors.push(e),
+                },
+                Meta::NameValue(ref nv) if nv.ident == "with" => {
+                    match parse_path(&nv.lit) {
+                        Ok(path) => result.with = Some(path),
+                        Err(e) => errors.push(e),
+                    }
+                    encoding_metas.push(meta.clone());
+                }
+                Meta::NameValue(ref nv) if nv.ident == "since" => match nv.lit {
+                    Lit::Int(ref i) if i.value() <= u64::from(u32::MAX) => {
+                        result.since = Some(i.value() as u32)
+                    }
+                    _ => errors.push(Error::new_spanned(
+                        &nv.lit,
+                        "expected a `u32` protocol version for `since`",
+                    )),
+                },
+                Meta::NameValue(ref nv) if nv.ident == "bits" => {
+                    match parse_bits(&nv.lit, 64) {
+                        Ok(bits) => result.bits = Some(bits),
+                        Err(e) => errors.push(e),
+                    }
+                    encoding_metas.push(meta.clone());
+                }
+                Meta::NameValue(ref nv) if nv.ident == "max_len" => {
+                    match parse_len(&nv.lit) {
+                        Ok(expr) => result.max_len = Some(expr),
+                        Err(e) => errors.push(e),
+                    }
+                    encoding_metas.push(meta.clone());
+                }
+                Meta::List(ref list) if list.ident == "quantize" => {
+                    match parse_quantize(list) {
+                        Ok(quantize) => result.quantize = Some(quantize),
+                        Err(mut e) => errors.append(&mut e),
+                    }
+                    encoding_metas.push(meta.clone());
+                }
+                _ => errors.push(Error::new_spanned(
+                    &meta,
+                    format!("unknown field attribute `{}`", meta.name()),
+                )),
+            }
+        }
+        if let Some((first, rest)) = encoding_metas.split_first() {
+            for meta in rest.iter() {
+                errors.push(Error::new_spanned(
+                    meta,
+                    format!(
+                        "`{}` and `{}` can't be used on the same field",
+                        first.name(),
+                        meta.name()
+                    ),
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(result)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Like `parse`, but for use after `validate` has already reported any errors.
+    pub fn from_field(field: &Field) -> Self {
+        Self::parse(field).expect("field attributes should've been validated")
+    }
+
+    /// Returns an expression for the field's default value.
+    pub fn default_expr(&self) -> QuoteTokenStream {
+        match self.default {
+            Some(ref path) => quote! { #path() },
+            None => quote! { ::std::default::Default::default() },
+        }
+    }
+}
+
+/// Parses the options of `#[serde(quantize(...))]`.
+fn parse_quantize(list: &MetaList) -> Result<Quantize, Vec<Error>> {
+    let mut min = None;
+    let mut max = None;
+    let mut bits = None;
+    let mut errors = vec![];
+    for nested in list.nested.iter() {
+        match *nested {
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "min" => {
+                match parse_number(&nv.lit) {
+                    Ok(expr) => min = Some(expr),
+                    Err(e) => errors.push(e),
+                }
+            }
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "max" => {
+                match parse_number(&nv.lit) {
+                    Ok(expr) => max = Some(expr),
+                    Err(e) => errors.push(e),
+                }
+            }
+            // Any more than 32 bits, and the steps would be finer than an `f32` can tell apart
+            // anyway.
+            NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "bits" => {
+                match parse_bits(&nv.lit, 32) {
+                    Ok(val) => bits = Some(val),
+                    Err(e) => errors.push(e),
+                }
+            }
+            _ => errors.push(Error::new_spanned(
+                nested,
+                "expected `min = ...`, `max = ...`, or `bits = ...`",
+            )),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    match (min, max, bits) {
+        (Some(min), Some(max), Some(bits)) => {
+            if let (Some(lo), Some(hi)) = (literal_value(&min), literal_value(&max)) {
+                if lo >= hi {
+                    return Err(vec![Error::new_spanned(
+                        list,
+                        "`min` has to be less than `max`",
+                    )]);
+                }
+            }
+            Ok(Quantize { min, max, bits })
+        }
+        _ => Err(vec![Error::new_spanned(
+            list,
+            "`quantize` needs a `min`, a `max`, and a number of `bits`",
+        )]),
+    }
+}
+
+/// Parses a number of bits between 1 and `max`.
+fn parse_bits(lit: &Lit, max: u64) -> Result<u32, Error> {
+    match *lit {
+        Lit::Int(ref i) if i.value() >= 1 && i.value() <= max => Ok(i.value() as u32),
+        _ => Err(Error::new_spanned(
+            lit,
+            format!("expected a number of bits from 1 to {}", max),
+        )),
+    }
+}
+
+/// Parses a numeric literal, or a string literal holding an expression for one (e.g.,
+/// `"-100.0"`, or `"-MAX_SPEED"`).  Negative numbers have to be written as strings, since only
+/// literals can appear in attributes.
+fn parse_number(lit: &Lit) -> Result<Expr, Error> {
+    match *lit {
+        Lit::Int(_) | Lit::Float(_) => Ok(Expr::Lit(ExprLit {
+            attrs: vec![],
+            lit: lit.clone(),
+        })),
+        Lit::Str(ref s) => s.parse(),
+        _ => Err(Error::new_spanned(
+            lit,
+            "expected a number, or a string containing an expression",
+        )),
+    }
+}
+
+/// Parses an integer literal, or a string literal holding an expression for one (e.g.,
+/// `"MAX_NAME_LEN"`).
+fn parse_len(lit: &Lit) -> Result<Expr, Error> {
+    match *lit {
+        Lit::Int(_) => Ok(Expr::Lit(ExprLit {
+            attrs: vec![],
+            lit: lit.clone(),
+        })),
+        Lit::Str(ref s) => s.parse(),
+        _ => Err(Error::new_spanned(
+            lit,
+            "expected a length, or a string containing an expression",
+        )),
+    }
+}
+
+/// Returns the value of `expr`, if it's a (possibly negated) numeric literal.
+fn literal_value(expr: &Expr) -> Option<f64> {
+    match *expr {
+        Expr::Lit(ExprLit {
+            lit: Lit::Int(ref i),
+            ..
+        }) => Some(i.value() as f64),
+        Expr::Lit(ExprLit {
+            lit: Lit::Float(ref f),
+            ..
+        }) => Some(f.value()),
+        Expr::Unary(ref unary) => match unary.op {
+            syn::UnOp::Neg(_) => literal_value(&unary.expr).map(|val| -val),
+            _ => None,
+        },
+        Expr::Paren(ref paren) => literal_value(&paren.expr),
+        _ => None,
+    }
+}
+
+/// Parses a string literal holding a path (e.g., `"my_mod::my_fn"`).
+fn parse_path(lit: &Lit) -> Result<Path, Error> {
+    match *lit {
+        Lit::Str(ref s) => s.parse(),
+        _ => Err(Error::new_spanned(
+            lit,
+            "expected a string containing a path",
+        )),
+    }
+}
+
+/// Parses a string literal full of where clause predicates (e.g., `"T: Foo, U: Bar"`).
+fn parse_where_predicates(lit: &Lit) -> Result<Vec<WherePredicate>, Error> {
+    match *lit {
+        Lit::Str(ref s) => Ok(s
+            .parse_with(Punctuated::<WherePredicate, Comma>::parse_terminated)?
+            .into_iter()
+            .collect()),
+        _ => Err(Error::new_spanned(
+            lit,
+            "expected a string of where clause predicates",
+        )),
+    }
+}
+
+/// Collects the options inside every `#[serde(...)]` attribute in `attrs`, pushing an error for
+/// anything that isn't an option.
+fn serde_metas(attrs: &[Attribute], errors: &mut Vec<Error>) -> Vec<Meta> {
+    let mut result = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        match attr.parse_meta() {
+            Ok(Meta::List(list)) => {
+                for nested in list.nested.into_iter() {
+                    match nested {
+                        NestedMeta::Meta(meta) => result.push(meta),
+                        NestedMeta::Literal(ref lit) => errors.push(Error::new_spanned(
+                            lit,
+                            "expected an option name in `#[serde(...)]`",
+                        )),
+                    }
+                }
+            }
+            Ok(_) => errors.push(Error::new_spanned(attr, "expected `#[serde(...)]`")),
+            Err(e) => errors.push(e),
+        }
+    }
+    result
+}
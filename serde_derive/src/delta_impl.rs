@@ -0,0 +1,163 @@
+use syn::Data::{Enum, Struct};
+use syn::{DataStruct, Index, Member};
+
+use crate::attr::{ContainerAttrs, FieldAttrs};
+use crate::serde_impl::{add_bounds, internal_ident, read_field, type_param_ident, write_field};
+use crate::QuoteTokenStream;
+
+/// Generates an implementation of the `SerializeDelta` trait, if it was asked for with
+/// `#[serde(delta)]`.
+pub fn impl_serialize_delta(ast: &syn::DeriveInput) -> QuoteTokenStream {
+    let attrs = ContainerAttrs::from_ast(ast);
+    if !attrs.delta {
+        return quote! {};
+    }
+
+    let ident = &ast.ident;
+    let mut generics = add_bounds(
+        &ast.generics,
+        &[
+            syn::parse_quote!(::serde::Serialize),
+            syn::parse_quote!(::serde::DeserializeOwned),
+            syn::parse_quote!(::std::cmp::PartialEq),
+        ],
+        &attrs.delta_bound,
+    );
+    let (ser_body, apply_body) = match ast.data {
+        Struct(ref ds) => struct_bodies(ds),
+        Enum(_) => {
+            // Enums are compared as a whole, so they need to be comparable as a whole.
+            let (_, ty_generics, _) = ast.generics.split_for_impl();
+            generics
+                .make_where_clause()
+                .predicates
+                .push(syn::parse_quote!(#ident #ty_generics: ::std::cmp::PartialEq));
+            enum_bodies()
+        }
+        _ => unreachable!(),
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let (base, out, de) = (
+        internal_ident("base"),
+        internal_ident("out"),
+        internal_ident("de"),
+    );
+    let (ser_ty, de_ty) = (
+        type_param_ident(&ast.generics, "S_"),
+        type_param_ident(&ast.generics, "D_"),
+    );
+    quote! {
+        impl #impl_generics ::serde::SerializeDelta for #ident #ty_generics #where_clause {
+            fn serialize_delta_into<#ser_ty: ::serde::Serializer + ?Sized>(
+                &self,
+                #base: &Self,
+                #out: &mut #ser_ty,
+            ) {
+                #ser_body
+            }
+
+            fn apply_delta_from<'de, #de_ty: ::serde::Deserializer<'de> + ?Sized>(
+                &mut self,
+                #de: &mut #de_ty,
+            ) -> ::std::result::Result<(), ::serde::DeserializeError> {
+                #apply_body
+            }
+        }
+    }
+}
+
+/// Returns the bodies of `serialize_delta_into` and `apply_delta_from` for a struct, which write
+/// a bitmask of the fields that changed, followed by those fields.
+fn struct_bodies(data_struct: &DataStruct) -> (QuoteTokenStream, QuoteTokenStream) {
+    let mut compare = quote! {};
+    let mut writes = quote! {};
+    let mut reads = quote! {};
+    let mut num_bits = 0u32;
+    let (base, out, de, mask) = (
+        internal_ident("base"),
+        internal_ident("out"),
+        internal_ident("de"),
+        internal_ident("mask"),
+    );
+    for (i, field) in data_struct.fields.iter().enumerate() {
+        let attrs = FieldAttrs::from_field(field);
+        if attrs.skip {
+            continue;
+        }
+        let member = match field.ident {
+            Some(ref ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        let bit = quote! { (1u64 << #num_bits) };
+        // Fields that aren't in the version being written never count as changed.
+        let version_check = match attrs.since {
+            Some(since) => quote! { #out.version() >= #since && },
+            None => quote! {},
+        };
+        compare.extend(quote! {
+            if #version_check self.#member != #base.#member {
+                #mask |= #bit;
+            }
+        });
+        let write = write_field(&quote! { &self.#member }, &attrs);
+        writes.extend(quote! {
+            if #mask & #bit != 0 {
+                #write
+            }
+        });
+        let read = read_field(&field.ty, &attrs);
+        reads.extend(quote! {
+            if #mask & #bit != 0 {
+                self.#member = #read;
+            }
+        });
+        num_bits += 1;
+    }
+
+    let ser_body = quote! {
+        #[allow(unused_mut)]
+        let mut #mask: u64 = 0;
+        #compare
+        #out.begin_tuple(1 + #mask.count_ones() as usize);
+        #out.write_bits(#mask, #num_bits);
+        #writes
+        #out.end_tuple();
+    };
+    let apply_body = quote! {
+        // The tuple's length depends on the mask, which hasn't been read yet, but none of the
+        // formats need it when reading.
+        #de.begin_tuple(0)?;
+        let #mask = #de.read_bits(#num_bits)?;
+        #reads
+        #de.end_tuple()
+    };
+    (ser_body, apply_body)
+}
+
+/// Returns the bodies of `serialize_delta_into` and `apply_delta_from` for an enum, which write a
+/// single changed bit, followed by the whole value if it changed.
+fn enum_bodies() -> (QuoteTokenStream, QuoteTokenStream) {
+    let (base, out, de, changed) = (
+        internal_ident("base"),
+        internal_ident("out"),
+        internal_ident("de"),
+        internal_ident("changed"),
+    );
+    let ser_body = quote! {
+        let #changed = self != #base;
+        #out.begin_tuple(1 + #changed as usize);
+        #out.write_bits(#changed as u64, 1);
+        if #changed {
+            ::serde::Serialize::serialize_into(self, #out);
+        }
+        #out.end_tuple();
+    };
+    let apply_body = quote! {
+        #de.begin_tuple(0)?;
+        if #de.read_bits(1)? == 1 {
+            *self = <Self as ::serde::Deserialize>::deserialize_from(#de)?;
+        }
+        #de.end_tuple()
+    };
+    (ser_body, apply_body)
+}
@@ -0,0 +1,72 @@
+//! Opt-in dumping of generated code, for when you want to see what the derive actually wrote.
+//!
+//! Set `SERDE_DERIVE_DUMP` to a comma-separated list of type names (or `*` for every type) and
+//! the expansion for each matching type ends up in
+//! `target/serde_derive/<crate name>/<type name>.rs`, run through `rustfmt` if it's installed.
+//! Cargo doesn't know the derive reads this variable, so you'll need to force the crate to
+//! rebuild (e.g., by touching one of its files) after changing it.
+
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use syn::Ident;
+
+use crate::QuoteTokenStream;
+
+const DUMP_VAR: &str = "SERDE_DERIVE_DUMP";
+
+/// Writes `tokens` out to the dump directory, if `ident` was asked for.  Failing to dump
+/// shouldn't fail the build, so problems are only printed as warnings.
+pub fn maybe_dump(ident: &Ident, tokens: &QuoteTokenStream) {
+    let requested = match env::var(DUMP_VAR) {
+        Ok(val) => val,
+        Err(_) => return,
+    };
+    let type_name = ident.to_string();
+    if !requested
+        .split(',')
+        .map(str::trim)
+        .any(|name| name == "*" || name == type_name)
+    {
+        return;
+    }
+
+    let target_dir = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("target"));
+    let crate_name = env::var("CARGO_PKG_NAME").unwrap_or_else(|_| String::from("unknown"));
+    let dir = target_dir.join("serde_derive").join(crate_name);
+    let path = dir.join(format!("{}.rs", type_name));
+
+    let source = tokens.to_string();
+    let source = pretty_print(&source).unwrap_or(source);
+    if let Err(e) = fs::create_dir_all(&dir).and_then(|_| fs::write(&path, source)) {
+        eprintln!(
+            "warning: couldn't dump `{}` to {}: {}",
+            type_name,
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Formats `source` with `rustfmt`, returning `None` if that isn't possible.
+fn pretty_print(source: &str) -> Option<String> {
+    let mut child = Command::new("rustfmt")
+        .args(["--edition", "2018", "--emit", "stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    child.stdin.take()?.write_all(source.as_bytes()).ok()?;
+    let output = child.wait_with_output().ok()?;
+    if output.status.success() {
+        String::from_utf8(output.stdout).ok()
+    } else {
+        None
+    }
+}
@@ -1,17 +1,26 @@
-use syn::{DataEnum, Ident};
+use std::collections::HashMap;
 
+use proc_macro2::Literal;
+use syn::{DataEnum, Error, Expr, ExprLit, Fields, Ident, Lit};
+
+use crate::attr::VariantAttrs;
 use crate::QuoteTokenStream;
 
-/// Generates implementations for `enum_tag` and `num_variants`.
+/// Generates implementations for `enum_tag`, `num_variants`, and (for enums without fields)
+/// `from_enum_tag`.
 pub fn impl_enum_methods(ast: &syn::DeriveInput, data_enum: &DataEnum) -> QuoteTokenStream {
     let enum_ident = &ast.ident;
     let enum_tag_impl = impl_enum_tag(enum_ident, data_enum);
     let num_variants_impl = impl_num_variants(data_enum);
+    let from_enum_tag_impl = impl_from_enum_tag(enum_ident, data_enum);
+
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
 
     quote! {
-        impl #enum_ident {
+        impl #impl_generics #enum_ident #ty_generics #where_clause {
             #enum_tag_impl
             #num_variants_impl
+            #from_enum_tag_impl
         }
     }
 }
@@ -20,21 +29,22 @@ pub fn impl_enum_methods(ast: &syn::DeriveInput, data_enum: &DataEnum) -> QuoteT
 /// variant).
 pub fn impl_enum_tag(enum_ident: &Ident, data_enum: &DataEnum) -> QuoteTokenStream {
     // Find a large enough type to identify all variants in this enum.
-    let num_variants = data_enum.variants.len();
-    let tag_type = calc_enum_tag_type(num_variants).unwrap();
+    let tags = enum_tags(data_enum);
+    let tag_type = calc_enum_tag_type(&tags).unwrap();
 
     // Iterate through enum fields and build match arms.
     let mut match_arms = quote!{};
-    for (variant_num, variant) in (0..num_variants).zip(data_enum.variants.iter()) {
+    for (tag, variant) in tags.into_iter().zip(data_enum.variants.iter()) {
         let variant_ident = &variant.ident;
+        let tag = Literal::u64_unsuffixed(tag);
 
         match_arms.extend(quote! {
-            #enum_ident::#variant_ident { .. } => (#variant_num as #tag_type),
+            #enum_ident::#variant_ident { .. } => #tag,
         });
     }
 
     quote! {
-        fn enum_tag(&self) -> #tag_type {
+        pub fn enum_tag(&self) -> #tag_type {
             match *self {
                 #match_arms
             }
@@ -42,29 +52,151 @@ pub fn impl_enum_tag(enum_ident: &Ident, data_enum: &DataEnum) -> QuoteTokenStre
     }
 }
 
-/// Generates an implementation of `num_variants` (a method returning the tag value for the
-/// current variant).
+/// Generates an implementation of `from_enum_tag` (the inverse of `enum_tag`), if none of the
+/// variants have fields.  Otherwise, there'd be nothing to fill the fields in with.
+pub fn impl_from_enum_tag(enum_ident: &Ident, data_enum: &DataEnum) -> QuoteTokenStream {
+    if !data_enum
+        .variants
+        .iter()
+        .all(|v| matches!(v.fields, Fields::Unit))
+    {
+        return quote! {};
+    }
+
+    let tags = enum_tags(data_enum);
+    let tag_type = calc_enum_tag_type(&tags).unwrap();
+
+    let mut match_arms = quote! {};
+    for (tag, variant) in tags.into_iter().zip(data_enum.variants.iter()) {
+        let variant_ident = &variant.ident;
+        let tag = Literal::u64_unsuffixed(tag);
+
+        match_arms.extend(quote! {
+            #tag => ::std::option::Option::Some(#enum_ident::#variant_ident),
+        });
+    }
+
+    quote! {
+        pub fn from_enum_tag(tag: #tag_type) -> ::std::option::Option<Self> {
+            match tag {
+                #match_arms
+                _ => ::std::option::Option::None,
+            }
+        }
+    }
+}
+
+/// Generates an implementation of `num_variants` (a method returning how many variants the enum
+/// has).
+///
+/// Since tags can be pinned with `#[serde(tag = N)]`, this is *not* the largest tag plus one, so
+/// it can't be used to bound tags.  For enums without fields, `from_enum_tag` can tell whether a
+/// tag is valid instead.
 pub fn impl_num_variants(data_enum: &DataEnum) -> QuoteTokenStream {
     let num_variants = data_enum.variants.len();
     quote! {
-        fn num_variants() -> usize {
+        pub fn num_variants() -> usize {
             #num_variants
         }
     }
 }
 
-/// Returns the appropriate uint type token to tag an enum with `num_variants` variants.  If the
-/// enum has no variants, returns `None`.
-pub fn calc_enum_tag_type(num_variants: usize) -> Option<QuoteTokenStream> {
-    match num_bytes_required(num_variants as u64) {
-        1 => Some(quote!{ u8 }),
+/// Works out the wire tag for each variant of `data_enum`, in declaration order.
+///
+/// Like Rust's own discriminants, a variant's tag is whatever it's pinned to (with `Variant = 7`
+/// or `#[serde(tag = 7)]`), or one more than the variant before it, with the first variant
+/// starting at zero.  Pinning tags means adding variants in the middle of an enum won't change
+/// what the existing ones look like on the wire.
+pub fn variant_tags(data_enum: &DataEnum) -> Result<Vec<u64>, Vec<Error>> {
+    let mut tags = vec![];
+    let mut errors = vec![];
+    // Maps each tag to the variant that claimed it first.
+    let mut claimed: HashMap<u64, &Ident> = HashMap::new();
+    let mut next_tag = Some(0u64);
+    for variant in data_enum.variants.iter() {
+        let attr_tag = match VariantAttrs::parse(variant) {
+            Ok(attrs) => attrs.tag,
+            Err(mut e) => {
+                errors.append(&mut e);
+                None
+            }
+        };
+        let discriminant_tag = match variant.discriminant {
+            Some((_, ref expr)) => match *expr {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Int(ref i),
+                    ..
+                }) => Some(i.value()),
+                _ => {
+                    errors.push(Error::new_spanned(
+                        expr,
+                        "enum discriminants must be integer literals",
+                    ));
+                    None
+                }
+            },
+            None => None,
+        };
+        if attr_tag.is_some() && variant.discriminant.is_some() {
+            errors.push(Error::new_spanned(
+                &variant.ident,
+                "variant has both a discriminant and a `#[serde(tag = ...)]`",
+            ));
+        }
+
+        let tag = match attr_tag.or(discriminant_tag).or(next_tag) {
+            Some(tag) => tag,
+            None => {
+                errors.push(Error::new_spanned(
+                    &variant.ident,
+                    "enum tag overflowed `u64`",
+                ));
+                continue;
+            }
+        };
+        if let Some(other) = claimed.insert(tag, &variant.ident) {
+            errors.push(Error::new_spanned(
+                &variant.ident,
+                format!("tag {} is already used by `{}`", tag, other),
+            ));
+            // Keep pointing later duplicates at the first variant to use the tag.
+            claimed.insert(tag, other);
+        }
+        tags.push(tag);
+        next_tag = tag.checked_add(1);
+    }
+    if errors.is_empty() {
+        Ok(tags)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Like `variant_tags`, but for use after `validate` has already reported any errors.
+pub fn enum_tags(data_enum: &DataEnum) -> Vec<u64> {
+    variant_tags(data_enum).expect("enum tags should've been validated")
+}
+
+/// Returns the appropriate uint type token to hold every tag in `tags`.  If the enum has no
+/// variants, returns `None`.
+pub fn calc_enum_tag_type(tags: &[u64]) -> Option<QuoteTokenStream> {
+    let max_tag = *tags.iter().max()?;
+    match num_bytes_required(max_tag) {
+        0 | 1 => Some(quote!{ u8 }),
         2 => Some(quote!{ u16 }),
-        3 | 4 => Some(quote!{ u32 }),
-        5 | 6 | 7 | 8 => Some(quote!{ u64 }),
+        3..=4 => Some(quote!{ u32 }),
+        5..=8 => Some(quote!{ u64 }),
         _ => unreachable!(),
     }
 }
 
+/// Returns the number of bits a bit-packed tag takes up, which is just enough to hold the largest
+/// tag in `tags`.  With the usual tags of `0..n`, that's `ceil(log2(n))`.
+pub fn calc_enum_tag_bits(tags: &[u64]) -> u32 {
+    let max_tag = tags.iter().cloned().max().unwrap_or(0);
+    64 - max_tag.leading_zeros()
+}
+
 /// Returns the number of bytes required to store `val`.
 fn num_bytes_required(val: u64) -> usize {
     for shift in (0..64).rev() {
@@ -75,3 +207,39 @@ fn num_bytes_required(val: u64) -> usize {
     0
 }
 
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn zero_bytes_required() {
+        assert_eq!(super::num_bytes_required(0), 0);
+    }
+
+    #[test]
+    fn one_byte_required_low() {
+        assert_eq!(super::num_bytes_required(1), 1);
+    }
+
+    #[test]
+    fn one_byte_required_high() {
+        assert_eq!(super::num_bytes_required(255), 1);
+    }
+
+    #[test]
+    fn two_bytes_required_low() {
+        assert_eq!(super::num_bytes_required(256), 2);
+    }
+
+    #[test]
+    fn two_bytes_required_high() {
+        assert_eq!(super::num_bytes_required(511), 2);
+    }
+
+    #[test]
+    fn tag_bits() {
+        assert_eq!(super::calc_enum_tag_bits(&[0]), 0);
+        assert_eq!(super::calc_enum_tag_bits(&[0, 1]), 1);
+        assert_eq!(super::calc_enum_tag_bits(&[0, 1, 2, 3]), 2);
+        assert_eq!(super::calc_enum_tag_bits(&[0, 1, 2, 3, 4]), 3);
+        assert_eq!(super::calc_enum_tag_bits(&[0, 300]), 9);
+    }
+}
@@ -0,0 +1,115 @@
+use proc_macro2::Literal;
+use syn::Data::{Enum, Struct};
+use syn::{DataEnum, Fields};
+
+use crate::attr::{ContainerAttrs, FieldAttrs};
+use crate::enum_impl::enum_tags;
+use crate::serde_impl::add_bounds;
+use crate::QuoteTokenStream;
+
+/// Generates an implementation of the `Schema` trait.
+pub fn impl_schema(ast: &syn::DeriveInput) -> QuoteTokenStream {
+    let ident = &ast.ident;
+    let shape = match ast.data {
+        Enum(ref de) => enum_shape(ast, de),
+        Struct(ref ds) => {
+            let fields = fields_schema(&ds.fields);
+            quote! {
+                ::serde::schema::Shape::Struct {
+                    name: stringify!(#ident),
+                    fields: #fields,
+                }
+            }
+        }
+        _ => unreachable!(),
+    };
+
+    let generics = add_bounds(
+        &ast.generics,
+        &[syn::parse_quote!(::serde::Schema)],
+        &ContainerAttrs::from_ast(ast).schema_bound,
+    );
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::serde::Schema for #ident #ty_generics #where_clause {
+            fn schema() -> ::serde::schema::Shape {
+                #shape
+            }
+        }
+    }
+}
+
+fn enum_shape(ast: &syn::DeriveInput, data_enum: &DataEnum) -> QuoteTokenStream {
+    let ident = &ast.ident;
+    let mut variants = quote! {};
+    for (tag, variant) in enum_tags(data_enum)
+        .into_iter()
+        .zip(data_enum.variants.iter())
+    {
+        let variant_ident = &variant.ident;
+        let tag = Literal::u64_unsuffixed(tag);
+        let fields = fields_schema(&variant.fields);
+        variants.extend(quote! {
+            ::serde::schema::Variant {
+                name: stringify!(#variant_ident),
+                tag: #tag,
+                fields: #fields,
+            },
+        });
+    }
+    quote! {
+        ::serde::schema::Shape::Enum {
+            name: stringify!(#ident),
+            variants: vec![#variants],
+        }
+    }
+}
+
+/// Returns an expression for a `Vec` describing every field that makes it onto the wire.
+fn fields_schema(fields: &Fields) -> QuoteTokenStream {
+    let mut result = quote! {};
+    for (i, field) in fields.iter().enumerate() {
+        let attrs = FieldAttrs::from_field(field);
+        if attrs.skip {
+            continue;
+        }
+        let name = match field.ident {
+            Some(ref ident) => ident.to_string(),
+            None => i.to_string(),
+        };
+        let ty = &field.ty;
+        let schema = quote! { <#ty as ::serde::Schema>::schema };
+        let encoding = if attrs.varint {
+            quote! { ::serde::schema::Encoding::Varint(#schema) }
+        } else if let Some(bits) = attrs.bits {
+            quote! { ::serde::schema::Encoding::Bits(#schema, #bits) }
+        } else if let Some(ref q) = attrs.quantize {
+            let (min, max, bits) = (&q.min, &q.max, q.bits);
+            quote! {
+                ::serde::schema::Encoding::Quantized {
+                    schema: #schema,
+                    min: (#min) as f64,
+                    max: (#max) as f64,
+                    bits: #bits,
+                }
+            }
+        } else if let Some(ref with) = attrs.with {
+            quote! { ::serde::schema::Encoding::With(stringify!(#with)) }
+        } else {
+            quote! { ::serde::schema::Encoding::Default(#schema) }
+        };
+        let since = match attrs.since {
+            Some(since) => quote! { ::std::option::Option::Some(#since) },
+            None => quote! { ::std::option::Option::None },
+        };
+        result.extend(quote! {
+            ::serde::schema::Field {
+                name: #name,
+                ty: stringify!(#ty),
+                encoding: #encoding,
+                since: #since,
+            },
+        });
+    }
+    quote! { vec![#result] }
+}
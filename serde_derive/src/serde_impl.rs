@@ -1,19 +1,19 @@
-use crate::proc_macro2::{Ident, Span};
+use crate::proc_macro2::{Ident, Literal};
 
 use syn::punctuated::Punctuated;
 use syn::token::Comma;
 use syn::Data::{Enum, Struct};
 use syn::Fields::*;
-use syn::Type::*;
-use syn::{DataEnum, DataStruct, Fields};
+use syn::{
+    DataEnum, DataStruct, Fields, GenericParam, Generics, LifetimeDef, Type, TypeParamBound,
+    WherePredicate,
+};
 
+use crate::attr::{ContainerAttrs, FieldAttrs};
+use crate::enum_impl::{calc_enum_tag_bits, calc_enum_tag_type, enum_tags};
 use crate::QuoteTokenStream;
-use crate::enum_impl::calc_enum_tag_type;
 
 /// Generates implementations for the `Serialize` and `Deserialize` traits.
-///
-/// Assumption: people don't suffix struct field names with underscores.  For those who do,
-/// kindly die.  :^)
 pub fn impl_serde_traits(ast: &syn::DeriveInput) -> QuoteTokenStream {
     match ast.data {
         Enum(ref de) => impl_serde_traits_enum(ast, de),
@@ -23,57 +23,89 @@ pub fn impl_serde_traits(ast: &syn::DeriveInput) -> QuoteTokenStream {
 }
 
 fn impl_serde_traits_enum(ast: &syn::DeriveInput, data_enum: &DataEnum) -> QuoteTokenStream {
-    // Find a large enough type to identify all variants in this enum.
-    let num_variants = data_enum.variants.len();
+    let tags = enum_tags(data_enum);
     let enum_ident = &ast.ident;
+    let (ser_generics, deser_generics) = impl_generics(ast);
+    let (ser_impl_generics, ty_generics, ser_where_clause) = ser_generics.split_for_impl();
+    let (deser_impl_generics, _, deser_where_clause) = deser_generics.split_for_impl();
+    // Use a large enough type to hold every variant's tag.
+    let tag_type = calc_enum_tag_type(&tags).unwrap();
+    let tag_bits = calc_enum_tag_bits(&tags);
+    let (out, de, variant_num) = (
+        internal_ident("out"),
+        internal_ident("de"),
+        internal_ident("variant_num"),
+    );
+    let (ser_ty, de_ty) = (
+        type_param_ident(&ast.generics, "S_"),
+        type_param_ident(&ast.generics, "D_"),
+    );
 
     // Iterate through enum fields and build method bodies.
     let mut ser_body = quote!{};
     let mut deser_body = quote!{};
-    for (variant_num, variant) in (0..num_variants).zip(data_enum.variants.iter()) {
+    let mut variant_list = quote!{};
+    for (tag, variant) in tags.iter().zip(data_enum.variants.iter()) {
         let variant_ident = &variant.ident;
+        let tag = Literal::u64_unsuffixed(*tag);
+        variant_list.extend(quote! { (stringify!(#variant_ident), #tag), });
         let (ser_deconstruct, deser_construct) = ser_deconstruct_deser_construct(
             &variant.fields,
             quote! { #enum_ident::#variant_ident },
         );
 
-        let ser_arm_body = impl_ser_body(&variant.fields);
-        let deser_arm_body = impl_deser_body(&variant.fields);
+        let ser_arm_body = impl_ser_body(variant_ident, &variant.fields);
+        let deser_arm_body = impl_deser_body(variant_ident, &variant.fields);
         ser_body.extend(quote! {
             #ser_deconstruct => {
-                result_.append(&mut self.enum_tag().serialize());
+                #out.write_variant_tag(
+                    stringify!(#enum_ident),
+                    stringify!(#variant_ident),
+                    ::std::convert::From::from(self.enum_tag()),
+                    ::std::mem::size_of::<#tag_type>(),
+                    #tag_bits,
+                );
                 #ser_arm_body
             },
         });
         deser_body.extend(quote! {
-            #variant_num => {
+            #tag => {
                 #deser_arm_body
-                (bytes_read_, #deser_construct)
+                ::std::result::Result::Ok(#deser_construct)
             },
         });
     }
 
     // Generate trait implementations.
-    let tag_type = calc_enum_tag_type(num_variants).unwrap();
     quote! {
-        impl ::serde::Serialize for #enum_ident {
-            fn serialize(&self) -> ::std::vec::Vec<u8> {
-                let mut result_: Vec<u8> = Vec::new();
+        impl #ser_impl_generics ::serde::Serialize for #enum_ident #ty_generics
+            #ser_where_clause
+        {
+            fn serialize_into<#ser_ty: ::serde::Serializer + ?Sized>(&self, #out: &mut #ser_ty) {
                 match *self {
                     #ser_body
                 };
-                result_
             }
         }
 
-        impl ::serde::Deserialize for #enum_ident {
-            fn deserialize(data: &[u8]) -> (usize, Self) {
-                // Anything that shadows `data` could cause issues.
-                let data_ = data;
-                let (mut bytes_read_, variant_num_) = <#tag_type>::deserialize(data_);
-                match (variant_num_ as usize) {
+        impl #deser_impl_generics ::serde::Deserialize<'de> for #enum_ident #ty_generics
+            #deser_where_clause
+        {
+            fn deserialize_from<#de_ty: ::serde::Deserializer<'de> + ?Sized>(
+                #de: &mut #de_ty,
+            ) -> ::std::result::Result<Self, ::serde::DeserializeError> {
+                let #variant_num = #de.read_variant_tag(
+                    stringify!(#enum_ident),
+                    &[#variant_list],
+                    ::std::mem::size_of::<#tag_type>(),
+                    #tag_bits,
+                )?;
+                match #variant_num {
                     #deser_body
-                    _ => panic!("invalid ID for enum variant (ID was {})", variant_num_),
+                    _ => ::std::result::Result::Err(::serde::DeserializeError::InvalidEnumTag {
+                        type_name: stringify!(#enum_ident),
+                        tag: #variant_num,
+                    }),
                 }
             }
         }
@@ -81,10 +113,18 @@ fn impl_serde_traits_enum(ast: &syn::DeriveInput, data_enum: &DataEnum) -> Quote
 }
 
 fn impl_serde_traits_struct(ast: &syn::DeriveInput, data_struct: &DataStruct) -> QuoteTokenStream {
-    let ser_body = impl_ser_body(&data_struct.fields);
-    let deser_body = impl_deser_body(&data_struct.fields);
-
     let struct_ident = &ast.ident;
+    let ser_body = impl_ser_body(struct_ident, &data_struct.fields);
+    let deser_body = impl_deser_body(struct_ident, &data_struct.fields);
+    let (out, de) = (internal_ident("out"), internal_ident("de"));
+    let (ser_ty, de_ty) = (
+        type_param_ident(&ast.generics, "S_"),
+        type_param_ident(&ast.generics, "D_"),
+    );
+
+    let (ser_generics, deser_generics) = impl_generics(ast);
+    let (ser_impl_generics, ty_generics, ser_where_clause) = ser_generics.split_for_impl();
+    let (deser_impl_generics, _, deser_where_clause) = deser_generics.split_for_impl();
     let (ser_deconstruct, deser_construct) = {
         let (ser_deconstruct, deser_construct) =
             ser_deconstruct_deser_construct(&data_struct.fields, quote! { #struct_ident });
@@ -98,22 +138,23 @@ fn impl_serde_traits_struct(ast: &syn::DeriveInput, data_struct: &DataStruct) ->
 
     // Generate trait implementations.
     quote! {
-        impl ::serde::Serialize for #struct_ident {
-            fn serialize(&self) -> ::std::vec::Vec<u8> {
-                let mut result_: Vec<u8> = Vec::new();
+        impl #ser_impl_generics ::serde::Serialize for #struct_ident #ty_generics
+            #ser_where_clause
+        {
+            fn serialize_into<#ser_ty: ::serde::Serializer + ?Sized>(&self, #out: &mut #ser_ty) {
                 #ser_deconstruct
                 #ser_body
-                result_
             }
         }
 
-        impl ::serde::Deserialize for #struct_ident {
-            fn deserialize(data: &[u8]) -> (usize, Self) {
-                // Anything that shadows `data` could cause issues.
-                let data_ = data;
-                let mut bytes_read_: usize = 0;
+        impl #deser_impl_generics ::serde::Deserialize<'de> for #struct_ident #ty_generics
+            #deser_where_clause
+        {
+            fn deserialize_from<#de_ty: ::serde::Deserializer<'de> + ?Sized>(
+                #de: &mut #de_ty,
+            ) -> ::std::result::Result<Self, ::serde::DeserializeError> {
                 #deser_body
-                (bytes_read_, #deser_construct)
+                ::std::result::Result::Ok(#deser_construct)
             }
         }
     }
@@ -121,6 +162,59 @@ fn impl_serde_traits_struct(ast: &syn::DeriveInput, data_struct: &DataStruct) ->
 
 // Helpers
 
+/// Returns the generics for the `Serialize` and `Deserialize` impls, respectively.  By default,
+/// every type parameter gets bounded by the trait being implemented, but the user can replace
+/// those bounds with `#[serde(bound = "...")]`.
+///
+/// The `Deserialize` impl also gets a `'de` lifetime for the input, which has to outlive every
+/// lifetime of the type, since fields like `&'a str` are borrowed from it.
+fn impl_generics(ast: &syn::DeriveInput) -> (Generics, Generics) {
+    let attrs = ContainerAttrs::from_ast(ast);
+    let mut deser_generics = add_bounds(
+        &ast.generics,
+        &[syn::parse_quote!(::serde::Deserialize<'de>)],
+        &attrs.de_bound,
+    );
+    let mut de_lifetime: LifetimeDef = syn::parse_quote!('de);
+    for def in ast.generics.lifetimes() {
+        de_lifetime.bounds.push(def.lifetime.clone());
+    }
+    if !de_lifetime.bounds.is_empty() {
+        de_lifetime.colon_token = Some(Default::default());
+    }
+    deser_generics
+        .params
+        .insert(0, GenericParam::Lifetime(de_lifetime));
+    (
+        add_bounds(
+            &ast.generics,
+            &[syn::parse_quote!(::serde::Serialize)],
+            &attrs.ser_bound,
+        ),
+        deser_generics,
+    )
+}
+
+pub fn add_bounds(
+    generics: &Generics,
+    default_bounds: &[TypeParamBound],
+    custom_bound: &Option<Vec<WherePredicate>>,
+) -> Generics {
+    let mut result = generics.clone();
+    match *custom_bound {
+        Some(ref predicates) => result
+            .make_where_clause()
+            .predicates
+            .extend(predicates.iter().cloned()),
+        None => {
+            for param in result.type_params_mut() {
+                param.bounds.extend(default_bounds.iter().cloned());
+            }
+        }
+    }
+    result
+}
+
 // TODO: This name is progressing my cancer.
 /// Returns a tuple of pattern deconstruction for serializing and struct/enum construction for
 /// deserializing (to promote code reuse between the handling of structs and enums).
@@ -133,10 +227,15 @@ fn ser_deconstruct_deser_construct(
         Named(_) => {
             let mut field_list: Punctuated<QuoteTokenStream, Comma> = Punctuated::new();
             let mut assign_list: Punctuated<QuoteTokenStream, Comma> = Punctuated::new();
-            for field in fields.iter() {
+            for (i, field) in fields.iter().enumerate() {
                 let field_ident = field.ident.clone().unwrap();
-                let binding_ident = field_ident.to_internal_ident();
-                field_list.push(quote!{ ref #field_ident });
+                let binding_ident = binding_ident(i, field);
+                // Skipped fields don't get serialized, so we don't need a binding for them.
+                if FieldAttrs::from_field(field).skip {
+                    field_list.push(quote! { #field_ident: _ });
+                } else {
+                    field_list.push(quote! { #field_ident: ref #binding_ident });
+                }
                 assign_list.push(quote!{ #field_ident: #binding_ident });
             }
             (
@@ -148,10 +247,14 @@ fn ser_deconstruct_deser_construct(
         Unnamed(_) => {
             let mut pattern_list: Punctuated<QuoteTokenStream, Comma> = Punctuated::new();
             let mut arg_list: Punctuated<QuoteTokenStream, Comma> = Punctuated::new();
-            for (i, _) in fields.iter().enumerate() {
-                let field_ident = i.to_internal_ident();
-                pattern_list.push(quote!{ ref #field_ident });
-                arg_list.push(quote!{ #field_ident });
+            for (i, field) in fields.iter().enumerate() {
+                let binding_ident = binding_ident(i, field);
+                if FieldAttrs::from_field(field).skip {
+                    pattern_list.push(quote! { _ });
+                } else {
+                    pattern_list.push(quote! { ref #binding_ident });
+                }
+                arg_list.push(quote!{ #binding_ident });
             }
             (
                 quote! { #type_ident(#pattern_list) },
@@ -163,103 +266,178 @@ fn ser_deconstruct_deser_construct(
     }
 }
 
-fn impl_ser_body(fields: &Fields) -> QuoteTokenStream {
-    let mut result = quote!{};
-    for (i, field) in fields.iter().enumerate() {
-        let binding_ident = field.ident.clone().unwrap_or(i.to_internal_ident());
-        result.extend(quote! {
-            result_.append(&mut #binding_ident.serialize());
-        });
+/// Returns the `::serde::StructKind` matching the layout of `fields`.
+fn struct_kind(fields: &Fields) -> QuoteTokenStream {
+    match fields {
+        Named(_) => quote! { ::serde::StructKind::Named },
+        Unnamed(_) => quote! { ::serde::StructKind::Tuple },
+        Unit => quote! { ::serde::StructKind::Unit },
+    }
+}
+
+/// Returns the name passed to `begin_field` for the `i`th field.
+fn field_name(i: usize, field: &syn::Field) -> String {
+    match field.ident {
+        Some(ref ident) => ident.to_string(),
+        None => i.to_string(),
     }
-    result
 }
 
-fn impl_deser_body(fields: &Fields) -> QuoteTokenStream {
-    let mut result = quote!{};
+/// Generates code writing the fields of the struct or variant called `name`.
+fn impl_ser_body(name: &Ident, fields: &Fields) -> QuoteTokenStream {
+    let kind = struct_kind(fields);
+    let out = internal_ident("out");
+    let mut result = quote! {
+        #out.begin_struct(stringify!(#name), #kind);
+    };
     for (i, field) in fields.iter().enumerate() {
-        let type_tokens = match field.ty {
-            Array(ref a) => {
-                let elem_ty = &(*a.elem);
-                let arr_len = &a.len;
-                quote! { [#elem_ty; #arr_len] }
-            }
-            Path(ref p) => {
-                quote! { #p }
-            }
-            _ => unreachable!(),
+        let binding_ident = binding_ident(i, field);
+        let attrs = FieldAttrs::from_field(field);
+        if attrs.skip {
+            continue;
+        }
+        let field_name = field_name(i, field);
+        let write = write_field(&quote! { #binding_ident }, &attrs);
+        let write = quote! {
+            #out.begin_field(#field_name);
+            #write
         };
-        let binding_ident = (i, field.ident.clone()).to_internal_ident();
-        result.extend(quote! {
-            let deser_data_ = <#type_tokens>::deserialize(&data_[bytes_read_..]);
-            bytes_read_ += deser_data_.0;
-            let #binding_ident = deser_data_.1;
-        });
+        match attrs.since {
+            Some(since) => result.extend(quote! {
+                if #out.version() >= #since {
+                    #write
+                }
+            }),
+            None => result.extend(write),
+        }
     }
+    result.extend(quote! { #out.end_struct(); });
     result
 }
 
-trait ToInternalIdent {
-    /// Adds an underscore suffix and, if the base type "starts with" a number, we also need a prefix
-    /// underscore, because an identifier can't start with a number.
-    ///
-    /// Just for the record, I fucking hate this, but as long as our meta-variables are in the same
-    /// environment as base-level variables, idk how else to go about this.
-    fn to_internal_ident(&self) -> Ident;
-}
-
-impl ToInternalIdent for usize {
-    fn to_internal_ident(&self) -> Ident {
-        Ident::new(&format!("_{}_", *self), Span::call_site())
+/// Generates code reading the fields of the struct or variant called `name` into bindings.
+fn impl_deser_body(name: &Ident, fields: &Fields) -> QuoteTokenStream {
+    let kind = struct_kind(fields);
+    let de = internal_ident("de");
+    let mut result = quote! {
+        #de.begin_struct(stringify!(#name), #kind)?;
+    };
+    for (i, field) in fields.iter().enumerate() {
+        let ty = &field.ty;
+        let binding_ident = binding_ident(i, field);
+        let attrs = FieldAttrs::from_field(field);
+        let default_expr = attrs.default_expr();
+        if attrs.skip {
+            result.extend(quote! {
+                let #binding_ident: #ty = #default_expr;
+            });
+            continue;
+        }
+        let field_name = field_name(i, field);
+        let read = read_field(ty, &attrs);
+        let read = quote! {{
+            #de.begin_field(#field_name)?;
+            #read
+        }};
+        match attrs.since {
+            Some(since) => result.extend(quote! {
+                let #binding_ident: #ty = if #de.version() >= #since {
+                    #read
+                } else {
+                    #default_expr
+                };
+            }),
+            None => result.extend(quote! {
+                let #binding_ident: #ty = #read;
+            }),
+        }
     }
+    result.extend(quote! { #de.end_struct()?; });
+    result
 }
 
-impl ToInternalIdent for Ident {
-    fn to_internal_ident(&self) -> Ident {
-        let base_ident_str = self.to_string();
-        // Can't have an empty identifier so the `unwrap` can't fail.
-        if base_ident_str.chars().next().unwrap().is_numeric() {
-            Ident::new(&format!("_{}_", *self), Span::call_site())
-        } else {
-            Ident::new(&format!("{}_", *self), Span::call_site())
+/// Returns a statement writing `field` (an expression for a reference to the field's value) to
+/// `out`, in whichever encoding its attributes ask for.
+pub fn write_field(field: &QuoteTokenStream, attrs: &FieldAttrs) -> QuoteTokenStream {
+    let out = internal_ident("out");
+    if attrs.varint {
+        quote! { ::serde::Varint::serialize_varint(#field, #out); }
+    } else if let Some(bits) = attrs.bits {
+        quote! { ::serde::Bits::serialize_bits(#field, #bits, #out); }
+    } else if let Some(ref q) = attrs.quantize {
+        let (min, max, bits) = (&q.min, &q.max, q.bits);
+        quote! {
+            ::serde::Quantize::serialize_quantized(
+                #field,
+                (#min) as f64,
+                (#max) as f64,
+                #bits,
+                #out,
+            );
         }
+    } else if let Some(ref with) = attrs.with {
+        quote! { #with::serialize_into(#field, #out); }
+    } else {
+        quote! { ::serde::Serialize::serialize_into(#field, #out); }
     }
 }
 
-// For abstracting over whether we're generating bindings for a named-field struct or an
-// unnamed-field (tuple) struct.
-impl ToInternalIdent for (usize, Option<Ident>) {
-    fn to_internal_ident(&self) -> Ident {
-        match self.1 {
-            Some(ref id) => id.to_internal_ident(),
-            None => self.0.to_internal_ident(),
+/// Returns an expression reading a field of type `ty` from `de`, the same way `write_field` wrote
+/// it.  The type is always spelled out in a qualified path, so any type that can be written in a
+/// field can be read.
+pub fn read_field(ty: &Type, attrs: &FieldAttrs) -> QuoteTokenStream {
+    let de = internal_ident("de");
+    if attrs.varint {
+        quote! { <#ty as ::serde::Varint>::deserialize_varint(#de)? }
+    } else if let Some(bits) = attrs.bits {
+        quote! { <#ty as ::serde::Bits>::deserialize_bits(#bits, #de)? }
+    } else if let Some(ref q) = attrs.quantize {
+        let (min, max, bits) = (&q.min, &q.max, q.bits);
+        quote! {
+            <#ty as ::serde::Quantize>::deserialize_quantized(
+                (#min) as f64,
+                (#max) as f64,
+                #bits,
+                #de,
+            )?
         }
+    } else if let Some(ref max_len) = attrs.max_len {
+        quote! { <#ty as ::serde::MaxLen>::deserialize_max_len((#max_len) as usize, #de)? }
+    } else if let Some(ref with) = attrs.with {
+        quote! { #with::deserialize_from(#de)? }
+    } else {
+        quote! { <#ty as ::serde::Deserialize>::deserialize_from(#de)? }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    #[test]
-    fn zero_bytes_required() {
-        assert_eq!(super::num_bytes_required(0), 0);
-    }
-
-    #[test]
-    fn one_byte_required_low() {
-        assert_eq!(super::num_bytes_required(1), 1);
-    }
-
-    #[test]
-    fn one_byte_required_high() {
-        assert_eq!(super::num_bytes_required(255), 1);
-    }
+/// Returns an identifier for a local variable in generated code.  It gets a mixed-site span, so
+/// it can't be seen by (or shadow) anything the user wrote, like a field or constant with the same
+/// name.
+pub fn internal_ident(name: &str) -> Ident {
+    Ident::new(name, proc_macro::Span::mixed_site().into())
+}
 
-    #[test]
-    fn two_bytes_required_low() {
-        assert_eq!(super::num_bytes_required(256), 2);
+/// Returns a name for a type parameter of a generated method.  Unlike local variables, type
+/// parameters aren't hidden by a mixed-site span, so the name is lengthened until it doesn't clash
+/// with any of the user's generics.
+pub fn type_param_ident(generics: &syn::Generics, name: &str) -> Ident {
+    let mut name = name.to_string();
+    while generics.type_params().any(|param| param.ident == name)
+        || generics.const_params().any(|param| param.ident == name)
+    {
+        name.push('_');
     }
+    internal_ident(&name)
+}
 
-    #[test]
-    fn two_bytes_required_high() {
-        assert_eq!(super::num_bytes_required(511), 2);
+/// Returns the binding holding the `i`th field while it's being serialized or deserialized.  The
+/// `__` prefix keeps it apart from the other internal identifiers, like `out` and `de`.
+fn binding_ident(i: usize, field: &syn::Field) -> Ident {
+    match field.ident {
+        // Raw identifiers like `r#type` can't be glued onto a prefix.
+        Some(ref ident) => {
+            internal_ident(&format!("__{}", ident.to_string().trim_start_matches("r#")))
+        }
+        None => internal_ident(&format!("__{}", i)),
     }
 }
@@ -1,53 +1,97 @@
-use syn::Data::{Enum, Struct};
-use syn::Fields::*;
+use syn::Data::{Enum, Struct, Union};
 use syn::Type::*;
-use syn::{DataEnum, DataStruct, Fields};
+use syn::{DataEnum, DataStruct, Error, Fields};
 
-// TODO: Just log errors and don't panic immediately.
+use crate::attr::{ContainerAttrs, FieldAttrs};
+use crate::enum_impl::variant_tags;
 
-/// Checks `ast` to ensure this proc macro is only used on valid constructs.  If any checks fail,
-/// we panic and let the spaghetti spill out of our pockets.
-pub fn validate(ast: &syn::DeriveInput) {
+/// Checks `ast` to ensure this proc macro is only used on valid constructs.  Rather than bailing
+/// at the first problem, we collect an error for every offending item, so they can all be
+/// reported (and fixed) at once.
+pub fn validate(ast: &syn::DeriveInput) -> Result<(), Vec<Error>> {
+    let mut errors = vec![];
+    if let Err(mut e) = ContainerAttrs::parse(ast) {
+        errors.append(&mut e);
+    }
+    // The `Deserialize` impl adds a lifetime of its own for the input.
+    if let Some(def) = ast
+        .generics
+        .lifetimes()
+        .find(|def| def.lifetime.ident == "de")
+    {
+        errors.push(Error::new_spanned(
+            &def.lifetime,
+            "`'de` is reserved for the lifetime of the input being deserialized",
+        ));
+    }
     match ast.data {
-        Enum(ref de) => validate_enum(de),
-        Struct(ref ds) => validate_struct(ds),
-        _ => panic!("macro only runnable on enums and structs"),
+        Enum(ref de) => validate_enum(ast, de, &mut errors),
+        Struct(ref ds) => validate_struct(ast, ds, &mut errors),
+        Union(ref du) => errors.push(Error::new_spanned(
+            du.union_token,
+            "`Serde` can only be derived for enums and structs",
+        )),
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
     }
 }
 
-fn validate_enum(data_enum: &DataEnum) {
-    if data_enum.variants.len() == 0 {
-        panic!("empty enums not supported");
+fn validate_enum(ast: &syn::DeriveInput, data_enum: &DataEnum, errors: &mut Vec<Error>) {
+    if data_enum.variants.is_empty() {
+        errors.push(Error::new_spanned(
+            &ast.ident,
+            "`Serde` can't be derived for empty enums",
+        ));
+    }
+    if let Err(mut e) = variant_tags(data_enum) {
+        errors.append(&mut e);
     }
     for variant in data_enum.variants.iter() {
-        if variant.discriminant.is_some() {
-            panic!("enum discriminants not supported");
-        }
-        validate_fields(&variant.fields);
+        validate_fields(&variant.fields, errors);
     }
 }
 
-fn validate_struct(data_struct: &DataStruct) {
-    validate_fields(&data_struct.fields);
+fn validate_struct(ast: &syn::DeriveInput, data_struct: &DataStruct, errors: &mut Vec<Error>) {
+    validate_fields(&data_struct.fields, errors);
+    // Deltas mark which fields changed with the bits of a `u64`.
+    let delta = ContainerAttrs::parse(ast).is_ok_and(|attrs| attrs.delta);
+    let num_fields = data_struct
+        .fields
+        .iter()
+        .filter(|field| FieldAttrs::parse(field).map_or(true, |attrs| !attrs.skip))
+        .count();
+    if delta && num_fields > 64 {
+        errors.push(Error::new_spanned(
+            &ast.ident,
+            "`delta` only supports structs with up to 64 (non-skipped) fields",
+        ));
+    }
 }
 
-fn validate_fields(fields: &Fields) {
+fn validate_fields(fields: &Fields, errors: &mut Vec<Error>) {
     for field in fields.iter() {
-        match field.ty {
-            Slice(_) => panic!("slice types not allowed in struct/variant def"),
-            Ptr(_) => panic!("pointer types not allowed in struct/variant def"),
-            Reference(_) => panic!("reference types not allowed in struct/variant def"),
-            BareFn(_) => panic!("bare function types not allowed in struct/variant def"),
-            Never(_) => panic!("never types not allowed in struct/variant def"),
-            Tuple(_) => panic!("tuple types not allowed in struct/variant def"),
-            TraitObject(_) => panic!("dyn trait objects not allowed in struct/variant def"),
-            ImplTrait(_) => panic!("impl trait objects not allowed in struct/variant def"),
-            Paren(_) => panic!("parenthesized types not allowed in struct/variant def"),
-            Group(_) => panic!("type groups not allowed in struct/variant def"),
-            Infer(_) => panic!("underscore types not allowed in struct/variant def"),
-            Macro(_) => panic!("macro types not allowed in struct/variant def"),
-            Verbatim(_) => panic!("verbatim types not allowed in struct/variant def"),
-            _ => (),
+        if let Err(mut e) = FieldAttrs::parse(field) {
+            errors.append(&mut e);
+        }
+        let type_desc = match field.ty {
+            Slice(_) => "slice types",
+            Ptr(_) => "pointer types",
+            // Shared references are fine, since `&str` and `&[u8]` can be borrowed from the input.
+            Reference(ref r) if r.mutability.is_some() => "mutable reference types",
+            BareFn(_) => "bare function types",
+            Never(_) => "never types",
+            TraitObject(_) => "dyn trait objects",
+            ImplTrait(_) => "impl trait objects",
+            Infer(_) => "underscore types",
+            Verbatim(_) => "verbatim types",
+            _ => continue,
         };
+        errors.push(Error::new_spanned(
+            &field.ty,
+            format!("{} not allowed in struct/variant def", type_desc),
+        ));
     }
 }
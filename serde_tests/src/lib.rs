@@ -3,33 +3,109 @@ extern crate serde_derive;
 
 #[cfg(test)]
 mod tests {
-    use serde::{Deserialize, Serialize};
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+    use std::convert::TryFrom;
+    use std::fmt::Debug;
+    use std::marker::PhantomData;
+
+    use proptest::prelude::*;
+    use serde::schema::{self, Encoding, Shape};
+    use serde::{text, varint};
+    use serde::{
+        BitReader, BitWriter, Config, Deserialize, DeserializeError, DeserializeOwned,
+        Deserializer, Endian, Schema, Serialize, SerializeDelta, Serializer, SliceReader,
+        SliceWriter, UsizeWidth, Varint, DEFAULT_MAX_LEN,
+    };
     use serde_derive::Serde;
 
     #[test]
     fn serde_uint() {
         let test_val = 69u32;
-        assert_eq!(u32::deserialize(&test_val.serialize()).1, test_val);
+        assert_eq!(u32::deserialize(&test_val.serialize()).unwrap().1, test_val);
     }
 
     #[test]
     fn serde_float() {
         let test_val: f32 = 69.420;
-        assert_eq!(f32::deserialize(&test_val.serialize()).1, test_val);
+        assert_eq!(f32::deserialize(&test_val.serialize()).unwrap().1, test_val);
     }
 
     #[test]
     fn serde_int() {
         let test_cases = [69i32, std::i32::MIN, std::i32::MAX];
         for case in test_cases.iter() {
-            assert_eq!(i32::deserialize(&case.serialize()).1, *case);
+            assert_eq!(i32::deserialize(&case.serialize()).unwrap().1, *case);
         }
     }
 
     #[test]
     fn serde_array() {
         let test_arr = [0u32, 1, 2];
-        assert_eq!(<[u32; 3]>::deserialize(&test_arr.serialize()).1, test_arr);
+        assert_eq!(
+            <[u32; 3]>::deserialize(&test_arr.serialize()).unwrap().1,
+            test_arr
+        );
+    }
+
+    #[test]
+    fn serde_large_array() {
+        let mut test_arr = [0u16; 256];
+        for (i, val) in test_arr.iter_mut().enumerate() {
+            *val = i as u16 * 3;
+        }
+        let bytes = test_arr.serialize();
+        assert_eq!(bytes.len(), 512);
+        assert_eq!(
+            <[u16; 256]>::deserialize(&bytes).unwrap().1[..],
+            test_arr[..]
+        );
+    }
+
+    #[test]
+    fn serde_array_non_copy() {
+        let test_arr = [String::from("ayy"), String::from("lmao")];
+        assert_eq!(
+            <[String; 2]>::deserialize(&test_arr.serialize()).unwrap().1,
+            test_arr
+        );
+        assert_eq!(<[String; 0]>::deserialize(&[]).unwrap(), (0, []));
+    }
+
+    thread_local! {
+        static LIVE_TRACKERS: Cell<isize> = const { Cell::new(0) };
+    }
+
+    /// Keeps count of how many instances are alive, so we can check for leaks.
+    #[derive(Debug)]
+    struct DropTracker;
+
+    impl<'de> Deserialize<'de> for DropTracker {
+        fn deserialize_from<D: Deserializer<'de> + ?Sized>(
+            de: &mut D,
+        ) -> Result<Self, DeserializeError> {
+            u8::deserialize_from(de)?;
+            LIVE_TRACKERS.with(|c| c.set(c.get() + 1));
+            Ok(DropTracker)
+        }
+    }
+
+    impl Drop for DropTracker {
+        fn drop(&mut self) {
+            LIVE_TRACKERS.with(|c| c.set(c.get() - 1));
+        }
+    }
+
+    #[test]
+    fn deser_array_error_drops_partial() {
+        // Only enough bytes for two of the four elements.
+        assert!(<[DropTracker; 4]>::deserialize(&[0, 0]).is_err());
+        assert_eq!(LIVE_TRACKERS.with(|c| c.get()), 0);
+
+        let arr = <[DropTracker; 4]>::deserialize(&[0, 0, 0, 0]).unwrap().1;
+        assert_eq!(LIVE_TRACKERS.with(|c| c.get()), 4);
+        drop(arr);
+        assert_eq!(LIVE_TRACKERS.with(|c| c.get()), 0);
     }
 
     #[test]
@@ -39,7 +115,7 @@ mod tests {
 
         let test_struct = TestStruct {};
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -53,7 +129,7 @@ mod tests {
 
         let test_struct = TestStruct { x: 69 };
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -68,7 +144,7 @@ mod tests {
 
         let test_struct = TestStruct { x: 69, y: 420 };
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -83,7 +159,7 @@ mod tests {
 
         let test_struct = TestStruct { x: 69, y: 420 };
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -91,13 +167,19 @@ mod tests {
     #[test]
     fn serde_string() {
         let test_val = String::from("farts");
-        assert_eq!(String::deserialize(&test_val.serialize()).1, test_val);
+        assert_eq!(
+            String::deserialize(&test_val.serialize()).unwrap().1,
+            test_val
+        );
     }
 
     #[test]
     fn serde_vec() {
         let test_val = vec![0i32, 1, 2, 3, 4, 5];
-        assert_eq!(<Vec<i32>>::deserialize(&test_val.serialize()).1, test_val);
+        assert_eq!(
+            <Vec<i32>>::deserialize(&test_val.serialize()).unwrap().1,
+            test_val
+        );
     }
 
     #[test]
@@ -115,7 +197,7 @@ mod tests {
             z: String::from("farts"),
         };
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -127,7 +209,7 @@ mod tests {
 
         let test_struct = TestStruct(0, String::from("ayy"));
         assert_eq!(
-            TestStruct::deserialize(&test_struct.serialize()).1,
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
             test_struct
         );
     }
@@ -144,7 +226,10 @@ mod tests {
 
         let test_enum = TestEnum::Up;
 
-        assert_eq!(TestEnum::deserialize(&test_enum.serialize()).1, test_enum);
+        assert_eq!(
+            TestEnum::deserialize(&test_enum.serialize()).unwrap().1,
+            test_enum
+        );
     }
 
     #[test]
@@ -159,7 +244,10 @@ mod tests {
             s: String::from("ayy lmao"),
         };
 
-        assert_eq!(TestEnum::deserialize(&test_enum.serialize()).1, test_enum);
+        assert_eq!(
+            TestEnum::deserialize(&test_enum.serialize()).unwrap().1,
+            test_enum
+        );
     }
 
     #[test]
@@ -170,7 +258,10 @@ mod tests {
         }
 
         let test_enum = TestEnum::A(69);
-        assert_eq!(TestEnum::deserialize(&test_enum.serialize()).1, test_enum);
+        assert_eq!(
+            TestEnum::deserialize(&test_enum.serialize()).unwrap().1,
+            test_enum
+        );
     }
 
     #[test]
@@ -187,7 +278,10 @@ mod tests {
         }
 
         let test_enum = TestEnum::B(TestStruct { x: 1337 });
-        assert_eq!(TestEnum::deserialize(&test_enum.serialize()).1, test_enum);
+        assert_eq!(
+            TestEnum::deserialize(&test_enum.serialize()).unwrap().1,
+            test_enum
+        );
     }
 
     #[test]
@@ -213,4 +307,2018 @@ mod tests {
 
         assert_eq!(TestEnum::num_variants(), 3);
     }
+
+    #[test]
+    fn enum_discriminants() {
+        #[derive(Clone, Copy, Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A = 4,
+            B,
+            C = 2,
+        }
+
+        assert_eq!(TestEnum::A.enum_tag(), 4);
+        assert_eq!(TestEnum::B.enum_tag(), 5);
+        assert_eq!(TestEnum::C.enum_tag(), 2);
+        assert_eq!(TestEnum::B.serialize(), vec![5]);
+        assert_eq!(TestEnum::deserialize(&[2]), Ok((1, TestEnum::C)));
+        assert_eq!(
+            TestEnum::deserialize(&[0]),
+            Err(DeserializeError::InvalidEnumTag {
+                type_name: "TestEnum",
+                tag: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn enum_tag_attr() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            #[serde(tag = 1)]
+            A(u32),
+            // Declared in the middle, but doesn't disturb `A` or `B`.
+            #[serde(tag = 7)]
+            New {
+                x: u8,
+            },
+            #[serde(tag = 2)]
+            B(u32),
+            C,
+        }
+
+        assert_eq!(TestEnum::A(0).enum_tag(), 1);
+        assert_eq!(TestEnum::New { x: 0 }.enum_tag(), 7);
+        assert_eq!(TestEnum::B(0).enum_tag(), 2);
+        assert_eq!(TestEnum::C.enum_tag(), 3);
+        assert_eq!(TestEnum::num_variants(), 4);
+        assert_eq!(TestEnum::B(69).serialize(), vec![2, 0, 0, 0, 69]);
+        assert_eq!(
+            TestEnum::deserialize(&[7, 3]),
+            Ok((2, TestEnum::New { x: 3 }))
+        );
+    }
+
+    #[test]
+    fn enum_tag_type_fits_largest_tag() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A,
+            #[serde(tag = 300)]
+            B,
+        }
+
+        let _: u16 = TestEnum::A.enum_tag();
+        assert_eq!(TestEnum::B.serialize(), vec![1, 44]);
+        assert_eq!(TestEnum::deserialize(&[0, 0]), Ok((2, TestEnum::A)));
+    }
+
+    #[test]
+    fn from_enum_tag() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A,
+            B = 3,
+            C,
+        }
+
+        assert_eq!(TestEnum::from_enum_tag(0), Some(TestEnum::A));
+        assert_eq!(TestEnum::from_enum_tag(3), Some(TestEnum::B));
+        assert_eq!(TestEnum::from_enum_tag(4), Some(TestEnum::C));
+        assert_eq!(TestEnum::from_enum_tag(1), None);
+        for val in &[TestEnum::A, TestEnum::B, TestEnum::C] {
+            assert_eq!(TestEnum::from_enum_tag(val.enum_tag()).as_ref(), Some(val));
+        }
+    }
+
+    #[test]
+    fn deser_truncated_uint() {
+        assert_eq!(
+            u32::deserialize(&[0, 1]),
+            Err(DeserializeError::UnexpectedEof {
+                needed: 4,
+                remaining: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn deser_truncated_string() {
+        let mut bytes = String::from("farts").serialize();
+        bytes.pop();
+        assert!(String::deserialize(&bytes).is_err());
+    }
+
+    #[test]
+    fn deser_string_ignores_trailing_bytes() {
+        let mut bytes = String::from("farts").serialize();
+        let expected_len = bytes.len();
+        bytes.extend_from_slice(b"trailing");
+        assert_eq!(
+            String::deserialize(&bytes),
+            Ok((expected_len, String::from("farts")))
+        );
+    }
+
+    #[test]
+    fn deser_invalid_utf8() {
+        let mut bytes = 2usize.serialize();
+        bytes.extend_from_slice(&[0xc3, 0x28]);
+        assert_eq!(
+            String::deserialize(&bytes),
+            Err(DeserializeError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn deser_vec_length_past_end() {
+        let bytes = 1000usize.serialize();
+        assert!(<Vec<u32>>::deserialize(&bytes).is_err());
+    }
+
+    #[test]
+    fn deser_invalid_enum_tag() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A(u32),
+            B(u32),
+        }
+
+        assert_eq!(
+            TestEnum::deserialize(&[2, 0, 0, 0, 0]),
+            Err(DeserializeError::InvalidEnumTag {
+                type_name: "TestEnum",
+                tag: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn deser_truncated_struct() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            x: u8,
+            y: u32,
+        }
+
+        let bytes = TestStruct { x: 69, y: 420 }.serialize();
+        assert!(TestStruct::deserialize(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn serialize_into_slice() {
+        let test_val = vec![69u16, 420];
+        let mut buf = [0u8; 16];
+        let mut writer = SliceWriter::new(&mut buf);
+        test_val.serialize_into(&mut writer);
+        assert!(!writer.overflowed());
+        let len = writer.len();
+        assert_eq!(&buf[..len], &test_val.serialize()[..]);
+    }
+
+    #[test]
+    fn serialize_into_slice_overflow() {
+        let mut buf = [0u8; 4];
+        let mut writer = SliceWriter::new(&mut buf);
+        String::from("farts").serialize_into(&mut writer);
+        assert!(writer.overflowed());
+        assert_eq!(writer.len(), 13);
+    }
+
+    #[test]
+    fn deserialize_from_reader() {
+        let mut bytes = 69u32.serialize();
+        bytes.append(&mut String::from("farts").serialize());
+        let mut reader = SliceReader::new(&bytes);
+        assert_eq!(u32::deserialize_from(&mut reader), Ok(69));
+        assert_eq!(
+            String::deserialize_from(&mut reader),
+            Ok(String::from("farts"))
+        );
+        assert_eq!(reader.bytes_read(), bytes.len());
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    fn varint_round_trip<T: Varint + PartialEq + std::fmt::Debug>(val: T, expected_len: usize) {
+        let mut bytes = vec![];
+        val.serialize_varint(&mut bytes);
+        assert_eq!(
+            bytes.len(),
+            expected_len,
+            "wrong encoded length for {:?}",
+            val
+        );
+        let mut reader = SliceReader::new(&bytes);
+        assert_eq!(T::deserialize_varint(&mut reader), Ok(val));
+        assert_eq!(reader.remaining(), 0);
+    }
+
+    #[test]
+    fn varint_unsigned() {
+        varint_round_trip(0u32, 1);
+        varint_round_trip(127u32, 1);
+        varint_round_trip(128u32, 2);
+        varint_round_trip(16_383u32, 2);
+        varint_round_trip(16_384u32, 3);
+        varint_round_trip(u32::MAX, 5);
+        varint_round_trip(u64::MAX, 10);
+        varint_round_trip(1usize << 40, 6);
+    }
+
+    #[test]
+    fn varint_signed() {
+        varint_round_trip(0i32, 1);
+        varint_round_trip(-1i32, 1);
+        varint_round_trip(63i32, 1);
+        varint_round_trip(-64i32, 1);
+        varint_round_trip(64i32, 2);
+        varint_round_trip(i32::MIN, 5);
+        varint_round_trip(i64::MIN, 10);
+        varint_round_trip(i64::MAX, 10);
+    }
+
+    #[test]
+    fn varint_zigzag() {
+        assert_eq!(varint::zigzag_encode(0), 0);
+        assert_eq!(varint::zigzag_encode(-1), 1);
+        assert_eq!(varint::zigzag_encode(1), 2);
+        assert_eq!(varint::zigzag_encode(-2), 3);
+        assert_eq!(varint::zigzag_decode(3), -2);
+    }
+
+    #[test]
+    fn varint_containers() {
+        varint_round_trip(String::from("farts"), 6);
+        varint_round_trip(vec![1u8, 2, 3], 4);
+    }
+
+    #[test]
+    fn varint_out_of_range() {
+        let mut bytes = vec![];
+        300u32.serialize_varint(&mut bytes);
+        let mut reader = SliceReader::new(&bytes);
+        assert_eq!(
+            u8::deserialize_varint(&mut reader),
+            Err(DeserializeError::InvalidVarint)
+        );
+    }
+
+    #[test]
+    fn varint_overlong() {
+        let bytes = [0xffu8; 11];
+        let mut reader = SliceReader::new(&bytes);
+        assert_eq!(
+            u64::deserialize_varint(&mut reader),
+            Err(DeserializeError::InvalidVarint)
+        );
+    }
+
+    #[test]
+    fn varint_truncated() {
+        let bytes = [0x80u8, 0x80];
+        let mut reader = SliceReader::new(&bytes);
+        assert!(u32::deserialize_varint(&mut reader).is_err());
+    }
+
+    #[test]
+    fn serde_varint_fields() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            #[serde(varint)]
+            idx: usize,
+            gen: u64,
+            #[serde(varint)]
+            name: String,
+            #[serde(varint)]
+            delta: i32,
+        }
+
+        let test_struct = TestStruct {
+            idx: 3,
+            gen: 1,
+            name: String::from("ayy"),
+            delta: -2,
+        };
+        let bytes = test_struct.serialize();
+        assert_eq!(bytes.len(), 1 + 8 + 4 + 1);
+        assert_eq!(TestStruct::deserialize(&bytes).unwrap(), (14, test_struct));
+    }
+
+    #[test]
+    fn serde_varint_tuple_variant() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A(#[serde(varint)] u32, u8),
+        }
+
+        let test_enum = TestEnum::A(5, 6);
+        let bytes = test_enum.serialize();
+        assert_eq!(bytes, vec![0, 5, 6]);
+        assert_eq!(TestEnum::deserialize(&bytes).unwrap().1, test_enum);
+    }
+
+    #[test]
+    fn serde_bool() {
+        for case in [true, false].iter() {
+            assert_eq!(bool::deserialize(&case.serialize()).unwrap(), (1, *case));
+        }
+        assert_eq!(
+            bool::deserialize(&[2]),
+            Err(DeserializeError::InvalidBool { byte: 2 })
+        );
+    }
+
+    #[test]
+    fn serde_option() {
+        let test_cases = [None, Some(69u32)];
+        for case in test_cases.iter() {
+            assert_eq!(
+                <Option<u32>>::deserialize(&case.serialize()).unwrap().1,
+                *case
+            );
+        }
+        assert!(<Option<u32>>::deserialize(&[7, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn serde_box() {
+        let test_val = Box::new(String::from("farts"));
+        assert_eq!(
+            <Box<String>>::deserialize(&test_val.serialize()).unwrap().1,
+            test_val
+        );
+    }
+
+    #[test]
+    fn serde_tuple() {
+        let test_val = (69u8, String::from("ayy"), -420i64);
+        assert_eq!(
+            <(u8, String, i64)>::deserialize(&test_val.serialize())
+                .unwrap()
+                .1,
+            test_val
+        );
+        assert_eq!(().serialize().len(), 0);
+    }
+
+    #[test]
+    fn serde_vec_deque() {
+        let test_val: VecDeque<u16> = vec![1, 2, 3].into_iter().collect();
+        assert_eq!(
+            <VecDeque<u16>>::deserialize(&test_val.serialize())
+                .unwrap()
+                .1,
+            test_val
+        );
+    }
+
+    #[test]
+    fn serde_sets() {
+        let hash_set: HashSet<u32> = vec![1, 5, 9].into_iter().collect();
+        assert_eq!(
+            <HashSet<u32>>::deserialize(&hash_set.serialize())
+                .unwrap()
+                .1,
+            hash_set
+        );
+        let btree_set: BTreeSet<String> = vec![String::from("a"), String::from("b")]
+            .into_iter()
+            .collect();
+        assert_eq!(
+            <BTreeSet<String>>::deserialize(&btree_set.serialize())
+                .unwrap()
+                .1,
+            btree_set
+        );
+    }
+
+    #[test]
+    fn serde_maps() {
+        let mut hash_map = HashMap::new();
+        hash_map.insert(String::from("oxygen"), 21.0f32);
+        hash_map.insert(String::from("plasma"), 0.5f32);
+        assert_eq!(
+            <HashMap<String, f32>>::deserialize(&hash_map.serialize())
+                .unwrap()
+                .1,
+            hash_map
+        );
+        let mut btree_map = BTreeMap::new();
+        btree_map.insert(3u32, vec![1u8]);
+        btree_map.insert(1u32, vec![]);
+        assert_eq!(
+            <BTreeMap<u32, Vec<u8>>>::deserialize(&btree_map.serialize())
+                .unwrap()
+                .1,
+            btree_map
+        );
+    }
+
+    #[test]
+    fn serde_struct_with_containers() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            alive: bool,
+            target: Option<u32>,
+            offset: (i32, i32),
+            inventory: HashMap<u32, Vec<String>>,
+        }
+
+        let mut inventory = HashMap::new();
+        inventory.insert(1, vec![String::from("crowbar")]);
+        let test_struct = TestStruct {
+            alive: true,
+            target: Some(3),
+            offset: (-1, 2),
+            inventory,
+        };
+        assert_eq!(
+            TestStruct::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+    }
+
+    #[test]
+    fn serde_tuple_in_enum_variant() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A((u8, u8)),
+            B { pair: (String, bool) },
+        }
+
+        let test_enum = TestEnum::B {
+            pair: (String::from("ayy"), false),
+        };
+        assert_eq!(
+            TestEnum::deserialize(&test_enum.serialize()).unwrap().1,
+            test_enum
+        );
+    }
+
+    #[test]
+    fn serde_generic_struct() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct Grid<T> {
+            width: u32,
+            cells: Vec<T>,
+        }
+
+        let test_struct = Grid {
+            width: 2,
+            cells: vec![String::from("floor"), String::from("wall")],
+        };
+        assert_eq!(
+            <Grid<String>>::deserialize(&test_struct.serialize())
+                .unwrap()
+                .1,
+            test_struct
+        );
+    }
+
+    #[test]
+    fn serde_generic_enum() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum Either<L, R> {
+            Left(L),
+            Right { val: R },
+        }
+
+        let test_enum: Either<u8, [u16; 2]> = Either::Right { val: [1, 2] };
+        assert_eq!(
+            <Either<u8, [u16; 2]>>::deserialize(&test_enum.serialize())
+                .unwrap()
+                .1,
+            test_enum
+        );
+        assert_eq!(test_enum.enum_tag(), 1);
+    }
+
+    #[test]
+    fn serde_generic_with_where_clause_and_lifetime() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct Tagged<'a, T>
+        where
+            T: Clone,
+        {
+            val: T,
+            _marker: PhantomData<&'a ()>,
+        }
+
+        let test_struct = Tagged {
+            val: 69u64,
+            _marker: PhantomData,
+        };
+        assert_eq!(
+            <Tagged<u64>>::deserialize(&test_struct.serialize())
+                .unwrap()
+                .1,
+            test_struct
+        );
+    }
+
+    #[test]
+    fn serde_custom_bound() {
+        // Not serializable, but it's only used as a marker.
+        #[derive(Debug, PartialEq)]
+        struct Marker;
+
+        #[derive(Debug, PartialEq, Serde)]
+        #[serde(bound = "")]
+        struct Handle<T> {
+            id: u32,
+            _marker: PhantomData<T>,
+        }
+
+        let test_struct: Handle<Marker> = Handle {
+            id: 7,
+            _marker: PhantomData,
+        };
+        assert_eq!(
+            <Handle<Marker>>::deserialize(&test_struct.serialize())
+                .unwrap()
+                .1,
+            test_struct
+        );
+    }
+
+    pub trait HasId {
+        type Id: std::fmt::Debug + PartialEq;
+    }
+
+    #[derive(Debug, PartialEq)]
+    pub struct Mob;
+
+    impl HasId for Mob {
+        type Id = u32;
+    }
+
+    #[test]
+    fn serde_qualified_field_type() {
+        #[derive(Debug, PartialEq, Serde)]
+        #[serde(bound(
+            serialize = "<T as HasId>::Id: Serialize",
+            deserialize = "<T as HasId>::Id: Deserialize<'de>",
+            schema = "<T as HasId>::Id: Schema"
+        ))]
+        struct Ref<T: HasId> {
+            id: <T as HasId>::Id,
+            pair: (u8, [(i8, u8); 2]),
+        }
+
+        let test_struct: Ref<Mob> = Ref {
+            id: 1337,
+            pair: (1, [(-1, 2), (-3, 4)]),
+        };
+        assert_eq!(
+            <Ref<Mob>>::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+    }
+
+    mod outer {
+        pub mod inner {
+            use serde_derive::Serde;
+
+            #[derive(Debug, PartialEq, Serde)]
+            pub struct Point {
+                pub x: i16,
+                pub y: i16,
+            }
+        }
+    }
+
+    macro_rules! int {
+        () => {
+            u32
+        };
+    }
+
+    #[test]
+    fn serde_any_field_type() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct Everything {
+            nested: outer::inner::Point,
+            absolute: ::std::vec::Vec<::std::vec::Vec<u8>>,
+            generic: Vec<Vec<outer::inner::Point>>,
+            qualified: Option<<Mob as HasId>::Id>,
+            array: [self::outer::inner::Point; 2],
+            tuple: (String, (<Mob as HasId>::Id,)),
+            mac: int!(),
+            #[serde(varint)]
+            varint: <Mob as HasId>::Id,
+        }
+
+        let point = |x, y| outer::inner::Point { x, y };
+        let test_struct = Everything {
+            nested: point(1, -1),
+            absolute: vec![vec![1, 2], vec![]],
+            generic: vec![vec![point(2, 3)]],
+            qualified: Some(7),
+            array: [point(4, 5), point(-6, -7)],
+            tuple: ("ayy".to_string(), (8,)),
+            mac: 9,
+            varint: 10,
+        };
+        assert_eq!(
+            Everything::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+        let text = text::to_string(&test_struct);
+        assert_eq!(text::from_str::<Everything>(&text), Ok(test_struct));
+    }
+
+    macro_rules! make_wrapper {
+        ( $name: ident, $ty: ty ) => {
+            #[derive(Debug, PartialEq, Serde)]
+            struct $name {
+                val: $ty,
+            }
+        };
+    }
+
+    make_wrapper!(WrappedVec, Vec<u8>);
+
+    #[test]
+    fn serde_macro_generated_field_type() {
+        let test_struct = WrappedVec { val: vec![1, 2, 3] };
+        assert_eq!(
+            WrappedVec::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+    }
+
+    #[test]
+    fn serde_field_names_dont_collide() {
+        fn de() -> u8 {
+            5
+        }
+
+        #[derive(Debug, PartialEq, Serde)]
+        #[serde(delta)]
+        struct Names {
+            out: u8,
+            de: u8,
+            mask: u8,
+            base: u8,
+            data_: Vec<u8>,
+            bytes_read_: usize,
+            result_: String,
+            __out: u8,
+            r#type: u8,
+            #[serde(varint)]
+            varint_: u32,
+            #[serde(skip, default = "de")]
+            skipped: u8,
+        }
+
+        #[derive(Debug, PartialEq, Serde)]
+        #[serde(delta)]
+        enum Variants {
+            Named { out: u8, de: u8, variant_num: u8 },
+            Tuple(u8, u8),
+        }
+
+        let test_struct = Names {
+            out: 1,
+            de: 2,
+            mask: 3,
+            base: 4,
+            data_: vec![5, 6],
+            bytes_read_: 7,
+            result_: "ayy".to_string(),
+            __out: 8,
+            r#type: 9,
+            varint_: 300,
+            skipped: 5,
+        };
+        assert_eq!(
+            Names::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+        let mut base = Names {
+            mask: 0,
+            base: 0,
+            ..Names::deserialize(&test_struct.serialize()).unwrap().1
+        };
+        base.apply_delta(&test_struct.delta(&base)).unwrap();
+        assert_eq!(base, test_struct);
+
+        for test_enum in [
+            Variants::Named {
+                out: 1,
+                de: 2,
+                variant_num: 3,
+            },
+            Variants::Tuple(4, 5),
+        ] {
+            assert_eq!(
+                Variants::deserialize(&test_enum.serialize()).unwrap().1,
+                test_enum
+            );
+        }
+    }
+
+    #[test]
+    fn serde_type_param_names_dont_collide() {
+        #[derive(Debug, PartialEq, Serde)]
+        #[serde(delta)]
+        struct Generic<S_, S__, D_> {
+            s: S_,
+            s_: S__,
+            d: D_,
+        }
+
+        let test_struct = Generic {
+            s: 1u8,
+            s_: 2u16,
+            d: -3i32,
+        };
+        assert_eq!(
+            Generic::deserialize(&test_struct.serialize()).unwrap().1,
+            test_struct
+        );
+        let mut base = Generic {
+            s: 0u8,
+            s_: 0u16,
+            d: 0i32,
+        };
+        base.apply_delta(&test_struct.delta(&base)).unwrap();
+        assert_eq!(base, test_struct);
+    }
+
+    #[test]
+    fn serde_skip_field() {
+        fn default_cache() -> Vec<u32> {
+            vec![1, 2, 3]
+        }
+
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            x: u8,
+            #[serde(skip)]
+            scratch: String,
+            #[serde(skip, default = "default_cache")]
+            cache: Vec<u32>,
+        }
+
+        let test_struct = TestStruct {
+            x: 69,
+            scratch: String::from("not sent"),
+            cache: vec![],
+        };
+        let bytes = test_struct.serialize();
+        assert_eq!(bytes, vec![69]);
+        assert_eq!(
+            TestStruct::deserialize(&bytes).unwrap().1,
+            TestStruct {
+                x: 69,
+                scratch: String::new(),
+                cache: vec![1, 2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn serde_skip_tuple_field() {
+        #[derive(Debug, PartialEq, Serde)]
+        enum TestEnum {
+            A(u8, #[serde(skip)] u32, u8),
+        }
+
+        let test_enum = TestEnum::A(1, 2, 3);
+        let bytes = test_enum.serialize();
+        assert_eq!(bytes, vec![0, 1, 3]);
+        assert_eq!(
+            TestEnum::deserialize(&bytes).unwrap().1,
+            TestEnum::A(1, 0, 3)
+        );
+    }
+
+    /// Sends an angle in degrees as a single byte.
+    mod compass {
+        use serde::{Deserialize, DeserializeError, Deserializer, Serialize, Serializer};
+
+        pub fn serialize_into<S: Serializer + ?Sized>(degrees: &f32, out: &mut S) {
+            ((*degrees / 360.0 * 256.0) as u8).serialize_into(out);
+        }
+
+        pub fn deserialize_from<'de, D: Deserializer<'de> + ?Sized>(
+            de: &mut D,
+        ) -> Result<f32, DeserializeError> {
+            Ok(f32::from(u8::deserialize_from(de)?) / 256.0 * 360.0)
+        }
+    }
+
+    #[test]
+    fn serde_with_field() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            #[serde(with = "compass")]
+            heading: f32,
+            speed: u8,
+        }
+
+        let test_struct = TestStruct {
+            heading: 90.0,
+            speed: 3,
+        };
+        let bytes = test_struct.serialize();
+        assert_eq!(bytes, vec![64, 3]);
+        assert_eq!(TestStruct::deserialize(&bytes).unwrap().1, test_struct);
+    }
+
+    #[test]
+    fn serde_since_field() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            x: u8,
+            #[serde(since = 2)]
+            y: u8,
+            #[serde(since = 3, default = "default_z")]
+            z: u16,
+        }
+
+        fn default_z() -> u16 {
+            420
+        }
+
+        let test_struct = TestStruct { x: 1, y: 2, z: 3 };
+
+        // The latest version has everything.
+        let bytes = test_struct.serialize();
+        assert_eq!(bytes, vec![1, 2, 0, 3]);
+        assert_eq!(TestStruct::deserialize(&bytes).unwrap().1, test_struct);
+
+        // Version 2 came before `z` was added.
+        let mut buf = [0u8; 8];
+        let mut writer = SliceWriter::new(&mut buf).with_version(2);
+        test_struct.serialize_into(&mut writer);
+        assert_eq!(writer.version(), 2);
+        let len = writer.len();
+        assert_eq!(&buf[..len], &[1, 2]);
+        let mut reader = SliceReader::new(&buf[..len]).with_version(2);
+        assert_eq!(
+            TestStruct::deserialize_from(&mut reader),
+            Ok(TestStruct { x: 1, y: 2, z: 420 })
+        );
+        assert_eq!(reader.remaining(), 0);
+
+        // Version 1 came before either of them.
+        let mut reader = SliceReader::new(&[1]).with_version(1);
+        assert_eq!(
+            TestStruct::deserialize_from(&mut reader),
+            Ok(TestStruct { x: 1, y: 0, z: 420 })
+        );
+    }
+
+    /// Makes sure the derive rejects everything it can't handle with a proper error, instead of
+    /// panicking.  The expected compiler output lives next to each case in `ui/`.
+    #[test]
+    fn compile_fail() {
+        trybuild::TestCases::new().compile_fail("ui/*.rs");
+    }
+
+    #[test]
+    fn schema_struct() {
+        #[derive(Serde)]
+        struct TestStruct {
+            x: u32,
+            #[serde(varint)]
+            y: u64,
+            #[serde(skip)]
+            _cache: Vec<u8>,
+            #[serde(since = 2)]
+            z: Option<String>,
+        }
+
+        let fields = match TestStruct::schema() {
+            Shape::Struct { name, fields } => {
+                assert_eq!(name, "TestStruct");
+                fields
+            }
+            _ => panic!("expected a struct schema"),
+        };
+        let names: Vec<_> = fields.iter().map(|f| (f.name, f.ty, f.since)).collect();
+        assert_eq!(
+            names,
+            vec![
+                ("x", "u32", None),
+                ("y", "u64", None),
+                ("z", "Option < String >", Some(2))
+            ]
+        );
+        assert!(matches!(fields[1].encoding, Encoding::Varint(_)));
+        match fields[2].encoding {
+            Encoding::Default(schema) => assert!(matches!(schema(), Shape::Option(_))),
+            _ => panic!("expected the default encoding"),
+        }
+    }
+
+    #[test]
+    fn schema_enum() {
+        #[derive(Serde)]
+        enum TestEnum {
+            A(u8),
+            #[serde(tag = 5)]
+            B {
+                x: i16,
+                y: i16,
+            },
+            C,
+        }
+
+        let variants = match TestEnum::schema() {
+            Shape::Enum { name, variants } => {
+                assert_eq!(name, "TestEnum");
+                variants
+            }
+            _ => panic!("expected an enum schema"),
+        };
+        let summary: Vec<_> = variants
+            .iter()
+            .map(|v| {
+                (
+                    v.name,
+                    v.tag,
+                    v.fields.iter().map(|f| f.name).collect::<Vec<_>>(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("A", 0, vec!["0"]),
+                ("B", 5, vec!["x", "y"]),
+                ("C", 6, vec![])
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprint_is_stable() {
+        // If this changes, every client and server built before the change gets locked out, so
+        // it had better be on purpose.
+        assert_eq!(
+            u32::fingerprint(),
+            schema::fingerprint(&Shape::Primitive("u32"))
+        );
+        assert_eq!(u32::fingerprint(), 0x5558_e7e0_6623_41b0);
+    }
+
+    #[test]
+    fn fingerprint_tracks_wire_changes() {
+        mod v1 {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                pub x: u32,
+                pub y: Vec<u8>,
+            }
+        }
+        mod same {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                pub x: u32,
+                pub y: Vec<u8>,
+                // Doesn't go over the wire, so it doesn't count.
+                #[serde(skip)]
+                pub _cache: u64,
+            }
+        }
+        mod retyped {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                pub x: u64,
+                pub y: Vec<u8>,
+            }
+        }
+        mod renamed {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                pub x: u32,
+                pub z: Vec<u8>,
+            }
+        }
+        mod varint {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                #[serde(varint)]
+                pub x: u32,
+                pub y: Vec<u8>,
+            }
+        }
+        mod nested {
+            use serde_derive::Serde;
+
+            #[derive(Serde)]
+            pub struct Msg {
+                pub x: u32,
+                pub y: Vec<i8>,
+            }
+        }
+
+        let base = v1::Msg::fingerprint();
+        assert_eq!(same::Msg::fingerprint(), base);
+        assert_ne!(retyped::Msg::fingerprint(), base);
+        assert_ne!(renamed::Msg::fingerprint(), base);
+        assert_ne!(varint::Msg::fingerprint(), base);
+        assert_ne!(nested::Msg::fingerprint(), base);
+    }
+
+    #[test]
+    fn fingerprint_recursive_type() {
+        #[derive(Serde)]
+        struct Node {
+            val: u8,
+            next: Option<Box<Node>>,
+        }
+
+        // Just needs to finish.
+        let _ = Node::fingerprint();
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct TextRender {
+        color: [f32; 4],
+        size: f64,
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    enum TextShape {
+        Empty,
+        Circle(f32),
+        Rect { w: u16, h: u16 },
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct TextScene {
+        name: String,
+        #[serde(varint)]
+        id: u64,
+        shapes: Vec<TextShape>,
+        parent: Option<u32>,
+        tags: BTreeMap<String, (i8, bool)>,
+        #[serde(with = "compass")]
+        heading: f32,
+    }
+
+    fn text_scene() -> TextScene {
+        let mut tags = BTreeMap::new();
+        tags.insert("big".to_string(), (-3, true));
+        tags.insert("quote\"d\n".to_string(), (4, false));
+        TextScene {
+            name: "Test Scene".to_string(),
+            id: 1 << 40,
+            shapes: vec![
+                TextShape::Empty,
+                TextShape::Circle(1.5),
+                TextShape::Rect { w: 2, h: 3 },
+            ],
+            parent: Some(7),
+            tags,
+            heading: 90.0,
+        }
+    }
+
+    #[test]
+    fn text_struct() {
+        let render = TextRender {
+            color: [0.3, 0.7, 0.3, 1.0],
+            size: 20.0,
+        };
+        let text = text::to_string(&render);
+        assert_eq!(
+            text,
+            "TextRender(\n    color: (0.3, 0.7, 0.3, 1.0),\n    size: 20.0,\n)"
+        );
+        assert_eq!(text::from_str::<TextRender>(&text), Ok(render));
+    }
+
+    #[test]
+    fn text_nested() {
+        let scene = text_scene();
+        let text = text::to_string(&scene);
+        assert_eq!(
+            text,
+            r#"TextScene(
+    name: "Test Scene",
+    id: 1099511627776,
+    shapes: [
+        Empty,
+        Circle(1.5),
+        Rect(
+            w: 2,
+            h: 3,
+        ),
+    ],
+    parent: Some(7),
+    tags: {
+        "big": (-3, true),
+        "quote\"d\n": (4, false),
+    },
+    heading: 64,
+)"#
+        );
+        assert_eq!(text::from_str::<TextScene>(&text), Ok(scene));
+    }
+
+    #[test]
+    fn text_primitives() {
+        assert_eq!(text::to_string(&None::<u8>), "None");
+        assert_eq!(text::to_string(&Vec::<u8>::new()), "[]");
+        assert_eq!(
+            text::to_string(&(1u8, "a\tb".to_string())),
+            "(1, \"a\\tb\")"
+        );
+        assert_eq!(text::from_str::<i64>("-42"), Ok(-42));
+        assert_eq!(text::from_str::<f32>("-1e3"), Ok(-1000.0));
+        assert_eq!(
+            text::from_str::<String>(r#""\u{e9}\x41""#),
+            Ok("\u{e9}A".to_string())
+        );
+        assert_eq!(
+            text::from_str::<Option<bool>>("Some(false)"),
+            Ok(Some(false))
+        );
+        let mut set = HashSet::new();
+        set.insert(3u16);
+        assert_eq!(
+            text::from_str::<HashSet<u16>>(&text::to_string(&set)),
+            Ok(set)
+        );
+    }
+
+    #[test]
+    fn text_is_lenient() {
+        // Struct names can be left off, and comments, whitespace and trailing commas are fine.
+        let text = "
+            // A hand-written scene.
+            (
+                name: \"Test Scene\", id: 1099511627776,
+                shapes: [Empty, Circle(1.5), Rect(w: 2, h: 3,),], // Trailing comma
+                parent: Some(7),
+                tags: {\"big\": (-3, true,), \"quote\\\"d\\n\": (4, false)},
+                heading: 64
+            )
+        ";
+        assert_eq!(text::from_str::<TextScene>(text), Ok(text_scene()));
+    }
+
+    #[test]
+    fn text_errors() {
+        fn syntax_error(line: usize, column: usize, message: &str) -> DeserializeError {
+            DeserializeError::Syntax {
+                line,
+                column,
+                message: message.to_string(),
+            }
+        }
+
+        assert_eq!(
+            text::from_str::<TextRender>("TextRender(\n  size: 20.0,\n)"),
+            Err(syntax_error(2, 3, "expected field `color`, found `size`"))
+        );
+        assert_eq!(
+            text::from_str::<TextRender>("Wrong(color: (0, 0, 0, 0), size: 1)"),
+            Err(syntax_error(1, 1, "expected `TextRender`, found `Wrong`"))
+        );
+        assert_eq!(
+            text::from_str::<TextShape>("Square(1)"),
+            Err(syntax_error(
+                1,
+                1,
+                "`Square` isn't a variant of `TextShape`"
+            ))
+        );
+        assert_eq!(
+            text::from_str::<u8>("300"),
+            Err(syntax_error(1, 1, "invalid `u8`: `300`"))
+        );
+        assert_eq!(
+            text::from_str::<Vec<u8>>("[1 2]"),
+            Err(syntax_error(1, 4, "expected `]`"))
+        );
+        assert_eq!(
+            text::from_str::<u8>("1 2"),
+            Err(syntax_error(1, 3, "expected end of input"))
+        );
+    }
+
+    #[test]
+    fn text_leaves_binary_alone() {
+        // The struct and variant hooks don't write anything in the binary format.
+        let bytes = text_scene().serialize();
+        assert_eq!(TextScene::deserialize(&bytes).unwrap().1, text_scene());
+        assert_eq!(
+            TextShape::Rect { w: 2, h: 3 }.serialize(),
+            vec![2, 0, 2, 0, 3]
+        );
+    }
+
+    /// Writes `val` with a `BitWriter`, checks how many bits it took, and reads it back.
+    fn bits_round_trip<T: Serialize + DeserializeOwned>(val: &T, bit_len: usize) -> T {
+        let mut writer = BitWriter::new();
+        val.serialize_into(&mut writer);
+        assert_eq!(writer.bit_len(), bit_len);
+        let bytes = writer.into_bytes();
+        assert_eq!(bytes.len(), bit_len.div_ceil(8));
+        let mut reader = BitReader::new(&bytes);
+        let result = T::deserialize_from(&mut reader).unwrap();
+        assert_eq!(reader.bits_read(), bit_len);
+        result
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    enum Facing {
+        North,
+        East,
+        South,
+        West,
+        Up,
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct PackedState {
+        alive: bool,
+        facing: Facing,
+        #[serde(bits = 3)]
+        ammo: u8,
+        #[serde(bits = 4)]
+        delta: i8,
+        target: Option<u16>,
+        #[serde(quantize(min = "-100.0", max = 100.0, bits = 12))]
+        x: f64,
+    }
+
+    #[test]
+    fn bits_packing() {
+        let state = PackedState {
+            alive: true,
+            facing: Facing::West,
+            ammo: 5,
+            delta: -3,
+            target: None,
+            x: 50.0,
+        };
+        let mut writer = BitWriter::new();
+        state.serialize_into(&mut writer);
+        assert_eq!(
+            writer.into_bytes(),
+            vec![0b1011_1011, 0b1010_1011, 0b1111_1111]
+        );
+
+        // 1 + 3 + 3 + 4 + 1 + 12 bits, rather than 1 + 1 + 1 + 1 + 1 + 8 bytes.
+        let result = bits_round_trip(&state, 24);
+        assert_eq!(result.x, 49.987789987789995);
+        assert_eq!(
+            result,
+            PackedState {
+                x: result.x,
+                ..state
+            }
+        );
+    }
+
+    #[test]
+    fn bits_unaligned() {
+        // Values without a bit-packed form are still written in full, just not byte-aligned.
+        assert_eq!(
+            bits_round_trip(&(true, 0x1234u16, "hi".to_string()), 1 + 16 + 64 + 16),
+            (true, 0x1234, "hi".to_string())
+        );
+        assert_eq!(bits_round_trip(&Some(-1i64), 65), Some(-1));
+        assert_eq!(
+            bits_round_trip(&vec![Facing::Up, Facing::North], 64 + 6),
+            vec![Facing::Up, Facing::North]
+        );
+    }
+
+    #[test]
+    fn bits_in_byte_formats() {
+        #[derive(Debug, PartialEq, Serde)]
+        struct TestStruct {
+            #[serde(bits = 12)]
+            x: u16,
+            #[serde(bits = 4)]
+            y: i32,
+            #[serde(quantize(min = 0, max = 1, bits = 8))]
+            z: f32,
+        }
+
+        let test_struct = TestStruct {
+            x: 0xabc,
+            y: -1,
+            z: 1.0,
+        };
+        // Each field gets rounded up to a whole number of bytes.
+        let bytes = test_struct.serialize();
+        assert_eq!(bytes, vec![0x0a, 0xbc, 0x0f, 0xff]);
+        assert_eq!(TestStruct::deserialize(&bytes).unwrap().1, test_struct);
+
+        // Too-large values are caught when reading.
+        assert_eq!(
+            TestStruct::deserialize(&[0x1a, 0xbc, 0x0f, 0xff]),
+            Err(DeserializeError::BitsOverflow {
+                val: 0x1abc,
+                bits: 12
+            })
+        );
+
+        #[derive(Debug, PartialEq, Serde)]
+        struct Flag {
+            #[serde(bits = 2)]
+            flag: bool,
+        }
+        assert_eq!(
+            Flag::deserialize(&[0x03]),
+            Err(DeserializeError::BitsOverflow { val: 3, bits: 2 })
+        );
+
+        // Text gets the exact values.
+        let test_struct = TestStruct {
+            x: 7,
+            y: -2,
+            z: 0.3,
+        };
+        let text = text::to_string(&test_struct);
+        assert_eq!(text, "TestStruct(\n    x: 7,\n    y: -2,\n    z: 0.3,\n)");
+        assert_eq!(text::from_str(&text), Ok(test_struct));
+    }
+
+    #[test]
+    fn quantize_clamps() {
+        use serde::bits::{dequantize, quantize};
+
+        assert_eq!(quantize(-5.0, 0.0, 10.0, 4), 0);
+        assert_eq!(quantize(15.0, 0.0, 10.0, 4), 15);
+        assert_eq!(quantize(f64::NAN, 0.0, 10.0, 4), 0);
+        assert_eq!(
+            dequantize(quantize(5.0, 0.0, 10.0, 4), 0.0, 10.0, 4),
+            5.333333333333333
+        );
+        assert_eq!(dequantize(15, 0.0, 10.0, 4), 10.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "quantize range 1..=1 is empty")]
+    fn quantize_empty_range() {
+        const LIMIT: f64 = 1.0;
+
+        #[derive(Serde)]
+        struct TestStruct {
+            #[serde(quantize(min = "LIMIT", max = "LIMIT", bits = 8))]
+            x: f64,
+        }
+
+        TestStruct { x: 1.0 }.serialize();
+    }
+
+    #[test]
+    #[should_panic(expected = "8 doesn't fit in 3 bits")]
+    fn bits_too_large() {
+        let state = PackedState {
+            alive: true,
+            facing: Facing::North,
+            ammo: 8,
+            delta: 0,
+            target: None,
+            x: 0.0,
+        };
+        state.serialize();
+    }
+
+    #[test]
+    fn bits_huge_length() {
+        let mut writer = BitWriter::new();
+        (true, usize::MAX).serialize_into(&mut writer);
+        let bytes = writer.into_bytes();
+        // Lift the length limit, so it's the end of the input that stops us.
+        let mut reader = BitReader::new(&bytes).with_max_len(usize::MAX);
+        assert_eq!(bool::deserialize_from(&mut reader), Ok(true));
+        assert_eq!(
+            String::deserialize_from(&mut reader),
+            Err(DeserializeError::UnexpectedEof {
+                needed: usize::MAX,
+                remaining: 0
+            })
+        );
+    }
+
+    #[test]
+    fn schema_bits() {
+        let fields = match PackedState::schema() {
+            Shape::Struct { fields, .. } => fields,
+            _ => panic!("expected a struct"),
+        };
+        assert!(matches!(fields[2].encoding, Encoding::Bits(_, 3)));
+        match fields[5].encoding {
+            Encoding::Quantized { min, max, bits, .. } => {
+                assert_eq!((min, max, bits), (-100.0, 100.0, 12))
+            }
+            _ => panic!("expected a quantized field"),
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serde)]
+    #[serde(delta)]
+    enum DeltaMode {
+        Idle,
+        Walking { speed: u8 },
+        Chasing(u32),
+    }
+
+    #[derive(Clone, Debug, PartialEq, Serde)]
+    #[serde(delta)]
+    struct DeltaState {
+        x: f32,
+        y: f32,
+        #[serde(varint)]
+        hp: i32,
+        alive: bool,
+        name: String,
+        target: Option<u16>,
+        path: Vec<(i8, i8)>,
+        mode: DeltaMode,
+        #[serde(skip)]
+        cache: u8,
+    }
+
+    fn delta_state() -> DeltaState {
+        DeltaState {
+            x: 1.0,
+            y: 2.0,
+            hp: 100,
+            alive: true,
+            name: "mob".to_string(),
+            target: None,
+            path: vec![],
+            mode: DeltaMode::Idle,
+            cache: 0,
+        }
+    }
+
+    #[test]
+    fn delta_changed_fields() {
+        let base = delta_state();
+        let new = DeltaState {
+            y: 2.5,
+            hp: 99,
+            ..base.clone()
+        };
+        // A one-byte mask with bits 1 and 2 set, then `y`, then `hp` as a zigzag varint.
+        let delta = new.delta(&base);
+        assert_eq!(delta, vec![0b110, 0x40, 0x20, 0, 0, 0xc6, 0x01]);
+
+        let mut result = base.clone();
+        assert_eq!(result.apply_delta(&delta), Ok(delta.len()));
+        assert_eq!(result, new);
+    }
+
+    #[test]
+    fn delta_unchanged() {
+        let base = delta_state();
+        assert_eq!(base.delta(&base), vec![0]);
+        // Skipped fields don't count as changes.
+        let new = DeltaState {
+            cache: 3,
+            ..base.clone()
+        };
+        assert_eq!(new.delta(&base), vec![0]);
+    }
+
+    #[test]
+    fn delta_enum() {
+        let base = DeltaMode::Walking { speed: 3 };
+        assert_eq!(base.delta(&base), vec![0]);
+        let new = DeltaMode::Chasing(7);
+        let delta = new.delta(&base);
+        assert_eq!(delta, vec![1, 2, 0, 0, 0, 7]);
+        let mut result = base;
+        result.apply_delta(&delta).unwrap();
+        assert_eq!(result, new);
+    }
+
+    #[test]
+    fn delta_bit_packed() {
+        let base = delta_state();
+        let new = DeltaState {
+            alive: false,
+            mode: DeltaMode::Idle,
+            ..base.clone()
+        };
+        // An 8-bit mask, then one bit for `alive`.
+        let mut writer = BitWriter::new();
+        new.serialize_delta_into(&base, &mut writer);
+        assert_eq!(writer.bit_len(), 9);
+        let bytes = writer.into_bytes();
+        let mut result = base;
+        result
+            .apply_delta_from(&mut BitReader::new(&bytes))
+            .unwrap();
+        assert_eq!(result, new);
+    }
+
+    #[test]
+    fn delta_text() {
+        let base = delta_state();
+        let new = DeltaState {
+            name: "boss".to_string(),
+            target: Some(4),
+            ..base.clone()
+        };
+        let mut writer = text::TextWriter::new();
+        new.serialize_delta_into(&base, &mut writer);
+        assert_eq!(writer.as_str(), "(48, \"boss\", Some(4))");
+        let mut result = base;
+        result
+            .apply_delta_from(&mut text::TextReader::new(writer.as_str()))
+            .unwrap();
+        assert_eq!(result, new);
+    }
+
+    fn arb_delta_mode() -> impl Strategy<Value = DeltaMode> {
+        prop_oneof![
+            Just(DeltaMode::Idle),
+            any::<u8>().prop_map(|speed| DeltaMode::Walking { speed }),
+            any::<u32>().prop_map(DeltaMode::Chasing),
+        ]
+    }
+
+    prop_compose! {
+        // Floats stay finite, since NaN never equals itself.
+        fn arb_delta_state()(
+            x in -1e6f32..1e6,
+            y in -1e6f32..1e6,
+            hp in any::<i32>(),
+            alive in any::<bool>(),
+            name in "[a-z]{0,8}",
+            target in any::<Option<u16>>(),
+            path in prop::collection::vec(any::<(i8, i8)>(), 0..4),
+            mode in arb_delta_mode(),
+        ) -> DeltaState {
+            DeltaState { x, y, hp, alive, name, target, path, mode, cache: 0 }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn delta_round_trip(base in arb_delta_state(), new in arb_delta_state()) {
+            let delta = new.delta(&base);
+            let mut result = base.clone();
+            prop_assert_eq!(result.apply_delta(&delta), Ok(delta.len()));
+            prop_assert_eq!(&result, &new);
+
+            let mut writer = BitWriter::new();
+            new.serialize_delta_into(&base, &mut writer);
+            let bytes = writer.into_bytes();
+            let mut result = base;
+            result.apply_delta_from(&mut BitReader::new(&bytes)).unwrap();
+            prop_assert_eq!(result, new);
+        }
+
+        #[test]
+        fn delta_against_self_is_empty(state in arb_delta_state()) {
+            prop_assert_eq!(state.delta(&state), vec![0]);
+        }
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct ChatMessage<'a> {
+        from: &'a str,
+        text: Option<&'a str>,
+        attachment: &'a [u8],
+    }
+
+    #[test]
+    fn borrowed_str_and_bytes() {
+        let bytes = ("hi", &[1u8, 2, 3][..]).serialize();
+        assert_eq!(bytes, (String::from("hi"), vec![1u8, 2, 3]).serialize());
+        let (len, (s, b)) = <(&str, &[u8])>::deserialize(&bytes).unwrap();
+        assert_eq!(len, bytes.len());
+        assert_eq!((s, b), ("hi", &[1u8, 2, 3][..]));
+        // Nothing was copied out of the input.
+        let range = bytes.as_ptr_range();
+        assert!(range.contains(&s.as_ptr()) && range.contains(&b.as_ptr()));
+    }
+
+    #[test]
+    fn borrowed_errors() {
+        let mut bytes = 2usize.serialize();
+        bytes.extend_from_slice(&[0xc3, 0x28]);
+        assert_eq!(
+            <&str>::deserialize(&bytes),
+            Err(DeserializeError::InvalidUtf8)
+        );
+        assert_eq!(
+            <&[u8]>::deserialize(&bytes[..9]),
+            Err(DeserializeError::UnexpectedEof {
+                needed: 2,
+                remaining: 1
+            })
+        );
+    }
+
+    #[test]
+    fn borrowed_derive() {
+        let message = ChatMessage {
+            from: "mob",
+            text: Some("grr"),
+            attachment: &[0xff],
+        };
+        let bytes = message.serialize();
+        assert_eq!(ChatMessage::deserialize(&bytes).unwrap().1, message);
+        // Borrowed and owned types are interchangeable on the wire.
+        assert_eq!(
+            <(&str, Option<&str>, &[u8])>::fingerprint(),
+            <(String, Option<String>, Vec<u8>)>::fingerprint()
+        );
+        let owned = <(String, Option<String>, Vec<u8>)>::deserialize(&bytes).unwrap();
+        assert_eq!(owned.1.serialize(), bytes);
+    }
+
+    #[test]
+    fn borrowed_text() {
+        assert_eq!(
+            text::from_str::<Vec<&str>>("[\"a\", \"b c\"]"),
+            Ok(vec!["a", "b c"])
+        );
+        // Escapes have to be copied to be unescaped, and byte slices are written element by
+        // element.
+        assert_eq!(
+            text::from_str::<&str>("\"a\\nb\""),
+            Err(DeserializeError::CannotBorrow)
+        );
+        assert_eq!(
+            text::from_str::<&[u8]>("[1, 2]"),
+            Err(DeserializeError::CannotBorrow)
+        );
+    }
+
+    #[test]
+    fn borrowed_bits() {
+        let mut writer = BitWriter::new();
+        ("hi", true, "there").serialize_into(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes);
+        assert_eq!(<&str>::deserialize_from(&mut reader), Ok("hi"));
+        assert_eq!(bool::deserialize_from(&mut reader), Ok(true));
+        // Past the `bool`, the string isn't byte-aligned anymore.
+        assert_eq!(
+            <&str>::deserialize_from(&mut reader),
+            Err(DeserializeError::CannotBorrow)
+        );
+    }
+
+    fn too_long(len: usize, max_len: usize) -> DeserializeError {
+        DeserializeError::TooLong { len, max_len }
+    }
+
+    #[test]
+    fn max_len_default() {
+        // Elements that don't take up any input can't run out of it, so a forged length has to be
+        // caught before we start reading them.
+        let len = 1 << 60;
+        let bytes = len.serialize();
+        let err = Some(too_long(len, DEFAULT_MAX_LEN));
+        assert_eq!(Vec::<()>::deserialize(&bytes).err(), err);
+        assert_eq!(VecDeque::<()>::deserialize(&bytes).err(), err);
+        assert_eq!(HashSet::<()>::deserialize(&bytes).err(), err);
+        assert_eq!(BTreeSet::<()>::deserialize(&bytes).err(), err);
+        assert_eq!(HashMap::<(), ()>::deserialize(&bytes).err(), err);
+        assert_eq!(BTreeMap::<(), ()>::deserialize(&bytes).err(), err);
+        assert_eq!(String::deserialize(&bytes).err(), err);
+        assert_eq!(<&str>::deserialize(&bytes).err(), err);
+        assert_eq!(<&[u8]>::deserialize(&bytes).err(), err);
+        let mut varint_bytes = vec![];
+        len.serialize_varint(&mut varint_bytes);
+        let mut reader = SliceReader::new(&varint_bytes);
+        assert_eq!(Vec::<()>::deserialize_varint(&mut reader).err(), err);
+
+        // Right up to the limit is fine.
+        let bytes = vec![(); DEFAULT_MAX_LEN].serialize();
+        assert_eq!(
+            Vec::<()>::deserialize(&bytes).unwrap().1.len(),
+            DEFAULT_MAX_LEN
+        );
+    }
+
+    #[test]
+    fn max_len_readers() {
+        let bytes = (vec![1u8, 2, 3], "abc").serialize();
+        let mut reader = SliceReader::new(&bytes).with_max_len(2);
+        assert_eq!(
+            Vec::<u8>::deserialize_from(&mut reader),
+            Err(too_long(3, 2))
+        );
+        let mut reader = SliceReader::new(&bytes).with_max_len(3);
+        assert_eq!(
+            <(Vec<u8>, &str)>::deserialize_from(&mut reader),
+            Ok((vec![1, 2, 3], "abc"))
+        );
+
+        let mut writer = BitWriter::new();
+        "abc".serialize_into(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes).with_max_len(2);
+        assert_eq!(String::deserialize_from(&mut reader), Err(too_long(3, 2)));
+
+        // Text sequences don't say how long they are up front, so they're counted as they're read.
+        let mut reader = text::TextReader::new("[1, 2, 3]").with_max_len(2);
+        assert_eq!(
+            Vec::<u8>::deserialize_from(&mut reader),
+            Err(too_long(3, 2))
+        );
+        let mut reader = text::TextReader::new("\"abc\"").with_max_len(2);
+        assert_eq!(String::deserialize_from(&mut reader), Err(too_long(3, 2)));
+        let mut reader = text::TextReader::new("\"abc\"").with_max_len(2);
+        assert_eq!(<&str>::deserialize_from(&mut reader), Err(too_long(3, 2)));
+    }
+
+    const NAME_LEN: usize = 4;
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct Limited<'a> {
+        #[serde(max_len = "NAME_LEN")]
+        name: String,
+        #[serde(max_len = 2)]
+        tag: &'a str,
+        #[serde(max_len = 3)]
+        scores: Option<Vec<u32>>,
+        #[serde(max_len = 100_000)]
+        blob: Vec<u8>,
+    }
+
+    #[test]
+    fn max_len_field() {
+        let limited = Limited {
+            name: "ayy".to_string(),
+            tag: "lm",
+            scores: Some(vec![1, 2, 3]),
+            blob: vec![0; DEFAULT_MAX_LEN + 1],
+        };
+        let bytes = limited.serialize();
+        assert_eq!(Limited::deserialize(&bytes).unwrap().1, limited);
+        // The fields' limits replace the deserializer's, whether they're lower or higher.
+        let mut reader = SliceReader::new(&bytes).with_max_len(1);
+        assert_eq!(
+            Limited::deserialize_from(&mut reader).as_ref(),
+            Ok(&limited)
+        );
+        let text = text::to_string(&limited);
+        assert_eq!(text::from_str::<Limited>(&text).as_ref(), Ok(&limited));
+
+        let long_name = Limited {
+            name: "ayyyy".to_string(),
+            ..Limited::deserialize(&bytes).unwrap().1
+        };
+        assert_eq!(
+            Limited::deserialize(&long_name.serialize()),
+            Err(too_long(5, NAME_LEN))
+        );
+        let long_scores = Limited {
+            scores: Some(vec![1, 2, 3, 4]),
+            ..Limited::deserialize(&bytes).unwrap().1
+        };
+        assert_eq!(
+            Limited::deserialize(&long_scores.serialize()),
+            Err(too_long(4, 3))
+        );
+        assert_eq!(
+            text::from_str::<Limited>(&text::to_string(&long_scores)),
+            Err(too_long(4, 3))
+        );
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    enum SaveKind {
+        Manual,
+        Auto = 300,
+    }
+
+    #[derive(Debug, PartialEq, Serde)]
+    struct SaveData {
+        name: String,
+        #[serde(varint)]
+        score: u32,
+        #[serde(bits = 12)]
+        level: u16,
+        kind: SaveKind,
+        time: f32,
+    }
+
+    const SAVE_CONFIG: Config = Config {
+        endian: Endian::Little,
+        usize_width: UsizeWidth::U32,
+        varint: false,
+    };
+
+    fn save_data() -> SaveData {
+        SaveData {
+            name: "ab".to_string(),
+            score: 300,
+            level: 0x123,
+            kind: SaveKind::Auto,
+            time: 1.0,
+        }
+    }
+
+    #[test]
+    fn config_default_is_network_format() {
+        let data = save_data();
+        let bytes = data.serialize();
+        assert_eq!(
+            bytes,
+            vec![
+                0, 0, 0, 0, 0, 0, 0, 2, b'a', b'b', // name
+                0xac, 0x02, // score
+                0x01, 0x23, // level
+                0x01, 0x2c, // kind
+                0x3f, 0x80, 0, 0, // time
+            ]
+        );
+        assert_eq!(data.serialize_with(Config::default()), bytes);
+    }
+
+    #[test]
+    fn config_save_format() {
+        let data = save_data();
+        let bytes = data.serialize_with(SAVE_CONFIG);
+        assert_eq!(
+            bytes,
+            vec![
+                2, 0, 0, 0, b'a', b'b', // name
+                0x2c, 0x01, 0, 0, // score
+                0x23, 0x01, // level
+                0x2c, 0x01, // kind
+                0, 0, 0x80, 0x3f, // time
+            ]
+        );
+        assert_eq!(
+            SaveData::deserialize_with(&bytes, SAVE_CONFIG),
+            Ok((bytes.len(), data))
+        );
+        // Nothing in the output says which config it was written with.
+        assert!(SaveData::deserialize(&bytes).is_err());
+
+        let mut buf = [0u8; 32];
+        let mut writer = SliceWriter::new(&mut buf).with_config(SAVE_CONFIG);
+        save_data().serialize_into(&mut writer);
+        assert_eq!(&buf[..bytes.len()], &bytes[..]);
+    }
+
+    #[test]
+    fn config_bits() {
+        let mut writer = BitWriter::new().with_config(SAVE_CONFIG);
+        (true, save_data(), vec![1u16, 2]).serialize_into(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes).with_config(SAVE_CONFIG);
+        assert_eq!(
+            <(bool, SaveData, Vec<u16>)>::deserialize_from(&mut reader),
+            Ok((true, save_data(), vec![1, 2]))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "doesn't fit in a 4-byte `usize`")]
+    fn config_usize_too_wide() {
+        vec![(); u32::MAX as usize + 1].serialize_with(SAVE_CONFIG);
+    }
+
+    // Property-based round trips for every built-in impl, in every format.
+
+    /// Checks that `val` comes back out of the binary, text, and bit-packed formats unchanged.
+    fn check_round_trip<T>(val: &T) -> Result<(), TestCaseError>
+    where
+        T: Serialize + DeserializeOwned + PartialEq + Debug,
+    {
+        let bytes = val.serialize();
+        let result = T::deserialize(&bytes);
+        prop_assert_eq!(
+            result.as_ref().map(|(len, val)| (*len, val)),
+            Ok((bytes.len(), val))
+        );
+        let text = text::to_string(val);
+        let result = text::from_str::<T>(&text);
+        prop_assert_eq!(result.as_ref(), Ok(val), "text was {}", text);
+        let mut writer = BitWriter::new();
+        val.serialize_into(&mut writer);
+        let bytes = writer.into_bytes();
+        let mut reader = BitReader::new(&bytes);
+        let result = T::deserialize_from(&mut reader);
+        prop_assert_eq!(result.as_ref(), Ok(val));
+        prop_assert!(reader.remaining_bits() < 8);
+        Ok(())
+    }
+
+    macro_rules! round_trip_tests {
+        ( $( $name: ident: $ty: ty = $strategy: expr; )* ) => {
+            proptest! {
+                $(
+                    #[test]
+                    fn $name(val in $strategy) {
+                        check_round_trip::<$ty>(&val)?;
+                    }
+                )*
+            }
+        };
+    }
+
+    round_trip_tests! {
+        round_trip_u8: u8 = any::<u8>();
+        round_trip_u16: u16 = any::<u16>();
+        round_trip_u32: u32 = any::<u32>();
+        round_trip_u64: u64 = any::<u64>();
+        round_trip_i8: i8 = any::<i8>();
+        round_trip_i16: i16 = any::<i16>();
+        round_trip_i32: i32 = any::<i32>();
+        round_trip_i64: i64 = any::<i64>();
+        round_trip_usize: usize = any::<usize>();
+        round_trip_bool: bool = any::<bool>();
+        // NaN never equals itself, so it gets its own test.
+        round_trip_f32: f32 = any::<f32>().prop_filter("NaN", |val| !val.is_nan());
+        round_trip_f64: f64 = any::<f64>().prop_filter("NaN", |val| !val.is_nan());
+        round_trip_string: String = any::<String>();
+        round_trip_vec: Vec<u16> = prop::collection::vec(any::<u16>(), 0..16);
+        round_trip_array: [i32; 4] = any::<[i32; 4]>();
+        round_trip_option: Option<u8> = any::<Option<u8>>();
+        round_trip_box: Box<i64> = any::<i64>().prop_map(Box::new);
+        round_trip_phantom: PhantomData<String> = Just(PhantomData);
+        round_trip_unit: () = Just(());
+        round_trip_tuple: (u8, String, bool) = any::<(u8, String, bool)>();
+        round_trip_vec_deque: VecDeque<u32> = prop::collection::vec_deque(any::<u32>(), 0..16);
+        round_trip_hash_set: HashSet<i16> = prop::collection::hash_set(any::<i16>(), 0..16);
+        round_trip_btree_set: BTreeSet<u64> = prop::collection::btree_set(any::<u64>(), 0..16);
+        round_trip_hash_map: HashMap<String, u8> =
+            prop::collection::hash_map(any::<String>(), any::<u8>(), 0..8);
+        round_trip_btree_map: BTreeMap<u8, Vec<bool>> = prop::collection::btree_map(
+            any::<u8>(),
+            prop::collection::vec(any::<bool>(), 0..4),
+            0..8,
+        );
+        round_trip_nested: Vec<Option<(String, [u8; 2])>> =
+            prop::collection::vec(any::<Option<(String, [u8; 2])>>(), 0..8);
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip_nan(bits in any::<u64>()) {
+            let val = f64::from_bits(bits | 0x7ff0_0000_0000_0001);
+            prop_assert!(val.is_nan());
+            // The binary format keeps the exact bits.
+            let bytes = val.serialize();
+            prop_assert_eq!(f64::deserialize(&bytes).unwrap().1.to_bits(), val.to_bits());
+            prop_assert!(text::from_str::<f64>(&text::to_string(&val)).unwrap().is_nan());
+        }
+
+        #[test]
+        fn round_trip_borrowed(s in any::<String>(), b in prop::collection::vec(any::<u8>(), 0..16)) {
+            let bytes = (&s, &b).serialize();
+            prop_assert_eq!(
+                <(&str, &[u8])>::deserialize(&bytes),
+                Ok((bytes.len(), (s.as_str(), b.as_slice())))
+            );
+        }
+
+        /// A `usize` is written as a `u64`, so a 32-bit target can be sent one that it can't hold,
+        /// which has to be an error rather than a silent truncation.
+        #[test]
+        fn usize_from_u64(val in prop_oneof![any::<u64>(), any::<u32>().prop_map(u64::from)]) {
+            let expected = usize::try_from(val)
+                .map_err(|_| DeserializeError::LengthOverflow { len: val });
+            let bytes = val.serialize();
+            prop_assert_eq!(usize::deserialize(&bytes).map(|(_, v)| v), expected.clone());
+
+            let mut bytes = vec![];
+            val.serialize_varint(&mut bytes);
+            let mut reader = SliceReader::new(&bytes);
+            prop_assert_eq!(usize::deserialize_varint(&mut reader), expected.clone());
+
+            // Lengths go through the same check.
+            if expected.is_err() {
+                prop_assert_eq!(
+                    <Vec<u8>>::deserialize(&val.serialize()),
+                    Err(DeserializeError::LengthOverflow { len: val })
+                );
+            }
+        }
+
+        /// Untrusted input has to be rejected with an error, never a panic.
+        #[test]
+        fn arbitrary_input_doesnt_panic(bytes in prop::collection::vec(any::<u8>(), 0..64)) {
+            let _ = TextScene::deserialize(&bytes);
+            let _ = DeltaState::deserialize(&bytes);
+            let _ = TextScene::deserialize_from(&mut BitReader::new(&bytes));
+            let _ = delta_state().apply_delta(&bytes);
+            let _ = text::from_str::<TextScene>(&String::from_utf8_lossy(&bytes));
+        }
+    }
 }
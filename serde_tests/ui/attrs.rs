@@ -0,0 +1,61 @@
+use serde_derive::Serde;
+
+#[derive(Serde)]
+#[serde(rename = "Other")]
+struct UnknownContainerOption {
+    x: u32,
+}
+
+#[derive(Serde)]
+#[serde(bound = "T Serialize")]
+struct BadBound<T> {
+    x: T,
+}
+
+#[derive(Serde)]
+#[serde(bound(serialize = "", sideways = ""))]
+struct BadBoundOption<T> {
+    x: T,
+}
+
+#[derive(Serde)]
+struct BadFieldAttrs {
+    #[serde(flatten)]
+    unknown: u32,
+    #[serde("skip")]
+    literal: u32,
+    #[serde(since = "2")]
+    since_str: u32,
+    #[serde(since = 5000000000)]
+    since_too_big: u32,
+    #[serde(default = 3)]
+    default_not_path: u32,
+    #[serde(with = "not a path")]
+    with_not_path: u32,
+    #[serde(varint, with = "some_mod")]
+    varint_with: u32,
+    #[serde(bits = 0)]
+    zero_bits: u32,
+    #[serde(bits = 3, varint)]
+    bits_varint: u32,
+    #[serde(quantize(min = -1.0, max = 1.0, bits = 8))]
+    negative_literal: f32,
+    #[serde(quantize(min = "-1.0", bits = 8))]
+    missing_max: f32,
+    #[serde(quantize(min = 2, max = 1, bits = 8))]
+    backwards: f32,
+    #[serde(quantize(min = 0, max = 1, bits = 40, step = 2))]
+    bad_quantize_options: f32,
+    #[serde(max_len = 1.5)]
+    float_max_len: Vec<u8>,
+    #[serde(varint, max_len = 10)]
+    varint_max_len: Vec<u8>,
+    #[serde(skip, with = "some_mod")]
+    skip_with: u32,
+    #[serde(skip, varint)]
+    skip_varint: u32,
+    #[serde(bits = 3, skip)]
+    bits_skip: u32,
+}
+
+fn main() {}
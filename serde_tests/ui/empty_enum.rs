@@ -0,0 +1,6 @@
+use serde_derive::Serde;
+
+#[derive(Serde)]
+enum Nothing {}
+
+fn main() {}
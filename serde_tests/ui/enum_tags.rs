@@ -0,0 +1,42 @@
+use serde_derive::Serde;
+
+#[derive(Serde)]
+enum Duplicates {
+    A,
+    #[serde(tag = 0)]
+    B,
+    C(u32),
+    #[serde(tag = 1)]
+    D { x: u32 },
+}
+
+#[derive(Serde)]
+enum BothTags {
+    #[serde(tag = 2)]
+    A = 2,
+}
+
+const THREE: isize = 3;
+
+#[derive(Serde)]
+enum NotLiteral {
+    A = THREE,
+    B = -1,
+}
+
+#[derive(Serde)]
+enum BadAttr {
+    #[serde(tag = "1")]
+    A,
+    #[serde(varint)]
+    B,
+}
+
+#[derive(Serde)]
+enum Overflow {
+    #[serde(tag = 18446744073709551615)]
+    A,
+    B,
+}
+
+fn main() {}
@@ -0,0 +1,37 @@
+use serde_derive::Serde;
+
+trait Shape {}
+
+#[derive(Serde)]
+struct Everything<'a> {
+    ptr: *const u8,
+    reference: &'a mut u8,
+    func: fn(u8) -> u8,
+    never: !,
+    opaque: impl Shape,
+    infer: _,
+}
+
+// Unsized types are only allowed as the last field, so these get their own structs.
+#[derive(Serde)]
+struct Slice {
+    slice: [u8],
+}
+
+#[derive(Serde)]
+struct Object {
+    object: dyn Shape,
+}
+
+#[derive(Serde)]
+enum Variants<'a> {
+    Tuple(&'a mut str),
+    Struct { ptr: *mut u8 },
+}
+
+#[derive(Serde)]
+struct Reserved<'de> {
+    name: &'de str,
+}
+
+fn main() {}
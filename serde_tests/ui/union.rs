@@ -0,0 +1,9 @@
+use serde_derive::Serde;
+
+#[derive(Serde)]
+union Bits {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
@@ -30,10 +30,11 @@ impl Game {
             socket: GameSocket::new(to_socket_addr(BIND_ADDR, SERVER_PORT)),
             clients: vec![],
         };
-        result
-            .ecs
-            .systems()
-            .append(&mut sys_vec![RandomMobUpdateSystem]);
+        for system in sys_vec![RandomMobUpdateSystem] {
+            if let Err(e) = result.ecs.add_system(system) {
+                panic!("couldn't schedule systems: {}", e);
+            }
+        }
         let _ = random_mob::new(&mut result.ecs);
         result
     }
@@ -41,11 +42,31 @@ impl Game {
     pub fn tick(&mut self) {
         // TODO: Should the logic tick and the network tick be ran in the same order as on the
         // client?
-        for (packet, src) in self.socket.poll().iter() {
+        while let Some((packet, src)) = self.socket.recv() {
+            let packet = match packet {
+                Ok(packet) => packet,
+                // A malformed packet shouldn't take us down with it.
+                Err(e) => {
+                    eprintln!("dropping malformed packet from {}: {}", src, e);
+                    continue;
+                }
+            };
             match packet {
-                Packet::Hello { name } => {
+                Packet::Hello { protocol, name } => {
+                    if protocol != protocol_fingerprint() {
+                        let reason = format!(
+                            "client protocol {:016x} doesn't match server protocol {:016x} \
+                             (are they built from the same revision?)",
+                            protocol,
+                            protocol_fingerprint()
+                        );
+                        eprintln!("rejected player \"{}\": {}", name, reason);
+                        self.socket
+                            .send_to(Packet::HelloReject { reason: &reason }, &src);
+                        continue;
+                    }
                     println!("player \"{}\" said hello", name);
-                    self.clients.push(src.clone());
+                    self.clients.push(src);
                     // TODO: Decide client ID.
                     self.socket.send_to(Packet::HelloAck {}, &src);
                     for entity in self.ecs.entities() {
@@ -63,7 +84,8 @@ impl Game {
                 }
                 Packet::HelloAck { .. }
                 | Packet::CreateEntity { .. }
-                | Packet::SetComponent { .. } => {
+                | Packet::SetComponent { .. }
+                | Packet::HelloReject { .. } => {
                     eprintln!("received invalid packet from client: {:?}", packet)
                 }
             };
@@ -71,7 +93,9 @@ impl Game {
 
         // TODO: Add *real* time deltas.
         let tick_config = TickConfig { dt: 1.0 };
-        self.ecs.tick(&tick_config);
+        if let Err(e) = self.ecs.tick(&tick_config) {
+            eprintln!("system skipped: {}", e);
+        }
     }
 }
 
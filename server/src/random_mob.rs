@@ -1,12 +1,12 @@
 extern crate rand;
 
-use std::any::TypeId;
-
 use self::rand::{thread_rng, Rng};
 
+use common::ecs::commands::Commands;
 use common::ecs::component::{PositionComponent, RenderComponent};
+use common::ecs::query::Query;
 use common::ecs::system::System;
-use common::ecs::{Ecs, Entity, EntityMap};
+use common::ecs::{Ecs, Entity};
 
 use super::TickConfig;
 
@@ -29,61 +29,61 @@ pub struct RandomMobComponent {
 
 pub fn new<T>(level: &mut Ecs<T>) -> Entity {
     let result = level.create_entity();
-    let mut comp_map = level.entity_map.borrow_mut(&result).unwrap();
-    comp_map.set(PositionComponent { x: 0.0, y: 0.0 });
-    comp_map.set(RandomMobComponent {
-        change_cnt: 0,
-        curr_dir: Dir::Up,
-    });
-    comp_map.set(RenderComponent {
-        color: COLOR,
-        size: SIZE,
-    });
+    level
+        .entity_map
+        .set(&result, PositionComponent { x: 0.0, y: 0.0 });
+    level.entity_map.set(
+        &result,
+        RandomMobComponent {
+            change_cnt: 0,
+            curr_dir: Dir::Up,
+        },
+    );
+    level.entity_map.set(
+        &result,
+        RenderComponent {
+            color: COLOR,
+            size: SIZE,
+        },
+    );
     result
 }
 
 pub struct RandomMobUpdateSystem;
 
 impl System<TickConfig> for RandomMobUpdateSystem {
-    fn comp_constraints(&self) -> Vec<TypeId> {
-        type_id_vec![RandomMobComponent, PositionComponent]
-    }
-
-    fn run(&self, tick_config: &TickConfig, entity_map: &mut EntityMap, entities: &Vec<Entity>) {
-        for entity in entities {
-            let mut comp_map = entity_map.borrow_mut(entity).unwrap();
+    type Query = (
+        &'static mut RandomMobComponent,
+        &'static mut PositionComponent,
+    );
 
-            let (dx, dy) = {
-                let rando_comp = comp_map.borrow_mut::<RandomMobComponent>();
-                if rando_comp.change_cnt == 0 {
-                    let mut rng = thread_rng();
-                    let rand_num = rng.gen_range(0, 4);
-                    rando_comp.curr_dir = match rand_num {
-                        0 => Dir::Up,
-                        1 => Dir::Down,
-                        2 => Dir::Left,
-                        3 => Dir::Right,
-                        _ => panic!(),
-                    };
-                }
-
-                let ms_dt = MOVE_SPEED * tick_config.dt;
-                let mut dx = 0.0f64;
-                let mut dy = 0.0f64;
-                match rando_comp.curr_dir {
-                    Dir::Up => dy += ms_dt,
-                    Dir::Down => dy -= ms_dt,
-                    Dir::Left => dx += ms_dt,
-                    Dir::Right => dx -= ms_dt,
+    fn run(
+        &self,
+        tick_config: &TickConfig,
+        mut query: Query<Self::Query>,
+        _commands: &mut Commands,
+    ) {
+        query.for_each(|_, (rando_comp, pos_comp)| {
+            if rando_comp.change_cnt == 0 {
+                let mut rng = thread_rng();
+                let rand_num = rng.gen_range(0, 4);
+                rando_comp.curr_dir = match rand_num {
+                    0 => Dir::Up,
+                    1 => Dir::Down,
+                    2 => Dir::Left,
+                    3 => Dir::Right,
+                    _ => panic!(),
                 };
-                rando_comp.change_cnt = (rando_comp.change_cnt + 1) % CHANGE_INTERVAL;
+            }
 
-                (dx, dy)
+            let ms_dt = MOVE_SPEED * tick_config.dt;
+            match rando_comp.curr_dir {
+                Dir::Up => pos_comp.y += ms_dt,
+                Dir::Down => pos_comp.y -= ms_dt,
+                Dir::Left => pos_comp.x += ms_dt,
+                Dir::Right => pos_comp.x -= ms_dt,
             };
-
-            let pos_comp = comp_map.borrow_mut::<PositionComponent>();
-            pos_comp.x += dx;
-            pos_comp.y += dy;
-        }
+            rando_comp.change_cnt = (rando_comp.change_cnt + 1) % CHANGE_INTERVAL;
+        });
     }
 }